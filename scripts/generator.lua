@@ -25,22 +25,8 @@ end
 
 if vim.fn.exists("g:syntax_on") == 0 then vim.cmd('syntax on') end
 
-vim.cmd('silent! filetype detect')
-
-local ft = vim.bo.filetype
-if ft and ft ~= "" then
-    vim.b.current_syntax = nil
-    pcall(vim.cmd, 'runtime! syntax/' .. ft .. '.vim')
-end
-
-local ts_active = false
 local ts_ok, ts = pcall(require, 'vim.treesitter')
-if ts_ok and pcall(ts.start) then ts_active = true end
-vim.cmd('redraw!')
-
 local hl_cache = {}
-local line_cache = {}
-local marks_by_line = {}
 
 local function get_ansi(hl_id)
     if not hl_id or hl_id <= 0 then return "" end
@@ -70,74 +56,120 @@ local function get_ansi(hl_id)
     return code
 end
 
-if ts_active then
-    local all_marks = api.nvim_buf_get_extmarks(0, -1, 0, -1, {details=true})
-    for _, m in ipairs(all_marks) do
-        local row = m[2] + 1
-        local col = m[3] + 1
-        local det = m[4]
-        if det.hl_group then
-            if not marks_by_line[row] then marks_by_line[row] = {} end
-            local end_col = (det.end_col and det.end_col + 1) or (col + 1)
-            local hl_id = (type(det.hl_group) == "string") and api.nvim_get_hl_id_by_name(det.hl_group) or det.hl_group
-            table.insert(marks_by_line[row], {col, end_col, hl_id})
+local function highlight(name, content, ft_override)
+    local buf = api.nvim_create_buf(false, true)
+    pcall(api.nvim_buf_set_name, buf, name)
+    api.nvim_buf_set_lines(buf, 0, -1, false, content)
+    api.nvim_set_current_buf(buf)
+
+    if ft_override and ft_override ~= "" then
+        vim.bo.filetype = ft_override
+    else
+        local detected = vim.filetype and vim.filetype.match({ buf = buf })
+        if detected then
+            vim.bo.filetype = detected
+        else
+            vim.cmd('silent! filetype detect')
         end
     end
-end
 
-local lines = api.nvim_buf_get_lines(0, 0, -1, false)
-local output = {}
+    local ft = vim.bo.filetype
+    if ft and ft ~= "" then
+        vim.b.current_syntax = nil
+        pcall(vim.cmd, 'runtime! syntax/' .. ft .. '.vim')
+    end
 
-for i, line in ipairs(lines) do
-    if #line == 0 then
-        table.insert(output, "")
-    else
-        if line_cache[line] then
-            table.insert(output, line_cache[line])
+    local ts_active = false
+    if ts_ok and pcall(ts.start, buf) then ts_active = true end
+    vim.cmd('redraw!')
+
+    local line_cache = {}
+    local marks_by_line = {}
+
+    if ts_active then
+        local all_marks = api.nvim_buf_get_extmarks(0, -1, 0, -1, {details=true})
+        for _, m in ipairs(all_marks) do
+            local row = m[2] + 1
+            local col = m[3] + 1
+            local det = m[4]
+            if det.hl_group then
+                if not marks_by_line[row] then marks_by_line[row] = {} end
+                local end_col = (det.end_col and det.end_col + 1) or (col + 1)
+                local hl_id = (type(det.hl_group) == "string") and api.nvim_get_hl_id_by_name(det.hl_group) or det.hl_group
+                table.insert(marks_by_line[row], {col, end_col, hl_id})
+            end
+        end
+    end
+
+    local lines = api.nvim_buf_get_lines(0, 0, -1, false)
+    local output = {}
+
+    for i, line in ipairs(lines) do
+        if #line == 0 then
+            table.insert(output, "")
         else
-            local buffer = {}
-            local ts_marks = marks_by_line[i]
-            
-            if ts_marks then
-                table.sort(ts_marks, function(a, b) return a[1] < b[1] end)
-                local current_col = 1
-                for _, m in ipairs(ts_marks) do
-                    local start_c, end_c, id = m[1], m[2], m[3]
-                    if start_c >= current_col then
-                        if start_c > current_col then table.insert(buffer, string.sub(line, current_col, start_c - 1)) end
-                        table.insert(buffer, get_ansi(id))
-                        table.insert(buffer, string.sub(line, start_c, end_c - 1))
-                        table.insert(buffer, "\27[0m")
-                        current_col = end_c
-                    end
-                end
-                if current_col <= #line then table.insert(buffer, string.sub(line, current_col)) end
+            if line_cache[line] then
+                table.insert(output, line_cache[line])
             else
-                local last_id = -1
-                local chunk_start = 1
-                for col = 1, #line do
-                    local id = fn.synID(i, col, 1)
-                    if id ~= last_id then
-                        if col > chunk_start then
-                            if last_id > 0 then table.insert(buffer, get_ansi(last_id)) end
-                            table.insert(buffer, string.sub(line, chunk_start, col - 1))
+                local buffer = {}
+                local ts_marks = marks_by_line[i]
+
+                if ts_marks then
+                    table.sort(ts_marks, function(a, b) return a[1] < b[1] end)
+                    local current_col = 1
+                    for _, m in ipairs(ts_marks) do
+                        local start_c, end_c, id = m[1], m[2], m[3]
+                        if start_c >= current_col then
+                            if start_c > current_col then table.insert(buffer, string.sub(line, current_col, start_c - 1)) end
+                            table.insert(buffer, get_ansi(id))
+                            table.insert(buffer, string.sub(line, start_c, end_c - 1))
+                            table.insert(buffer, "\27[0m")
+                            current_col = end_c
+                        end
+                    end
+                    if current_col <= #line then table.insert(buffer, string.sub(line, current_col)) end
+                else
+                    local last_id = -1
+                    local chunk_start = 1
+                    for col = 1, #line do
+                        local id = fn.synID(i, col, 1)
+                        if id ~= last_id then
+                            if col > chunk_start then
+                                if last_id > 0 then table.insert(buffer, get_ansi(last_id)) end
+                                table.insert(buffer, string.sub(line, chunk_start, col - 1))
+                            end
+                            chunk_start = col
+                            last_id = id
                         end
-                        chunk_start = col
-                        last_id = id
                     end
+                    if last_id > 0 then table.insert(buffer, get_ansi(last_id)) end
+                    table.insert(buffer, string.sub(line, chunk_start))
+                    table.insert(buffer, "\27[0m")
                 end
-                if last_id > 0 then table.insert(buffer, get_ansi(last_id)) end
-                table.insert(buffer, string.sub(line, chunk_start))
-                table.insert(buffer, "\27[0m")
+
+                local res = table.concat(buffer)
+                line_cache[line] = res
+                table.insert(output, res)
             end
-            
-            local res = table.concat(buffer)
-            line_cache[line] = res
-            table.insert(output, res)
         end
     end
+
+    pcall(api.nvim_buf_delete, buf, { force = true })
+    return table.concat(output, "\n") .. "\n"
+end
+
+local function detect(path)
+    local ok, head = pcall(fn.readfile, path, '', 200)
+    return vim.filetype.match({ filename = path, contents = ok and head or nil }) or ""
+end
+
+local function colors(groups)
+    local out = {}
+    for _, name in ipairs(groups) do
+        local hl = api.nvim_get_hl(0, { name = name, link = false })
+        table.insert(out, string.format("%s %d %d", name, hl.fg or -1, hl.bg or -1))
+    end
+    return table.concat(out, "\n") .. "\n"
 end
 
-io.stdout:write(table.concat(output, "\n"))
-io.stdout:write("\n")
-vim.cmd('qa!')
+_G.meow = { highlight = highlight, detect = detect, colors = colors }
@@ -0,0 +1,410 @@
+use std::ops::Range;
+use unicode_width::UnicodeWidthChar;
+
+const RESET: &str = "\x1b[0m";
+const TAB_STOP: usize = 8;
+
+/// Length of the escape sequence at the start of `s`, if it starts with one:
+/// a CSI sequence (`ESC [ ... final`), else ESC and the byte after it.
+fn escape_len(s: &[u8]) -> Option<usize> {
+    if s.first() != Some(&0x1b) {
+        return None;
+    }
+    if s.get(1) == Some(&b'[') {
+        let end = s[2..].iter().position(|b| (0x40..=0x7e).contains(b));
+        return Some(end.map_or(s.len(), |e| e + 3));
+    }
+    Some(s.len().min(2))
+}
+
+/// `line` without its escape sequences.
+pub fn strip(line: &str) -> String {
+    let bytes = line.as_bytes();
+    let mut plain = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match escape_len(&bytes[i..]) {
+            Some(len) => i += len,
+            None => {
+                plain.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&plain).into_owned()
+}
+
+/// `data` without its first `n` bytes of text; escape sequences are kept.
+pub fn skip_text(data: &[u8], n: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut left = n;
+    let mut i = 0;
+    while i < data.len() {
+        match escape_len(&data[i..]) {
+            Some(len) => {
+                out.extend_from_slice(&data[i..i + len]);
+                i += len;
+            }
+            None if left > 0 => {
+                left -= 1;
+                i += 1;
+            }
+            None => {
+                out.extend_from_slice(&data[i..]);
+                break;
+            }
+        }
+    }
+    out
+}
+
+/// Draws `style` over `spans`, byte ranges of `strip(line)`, keeping the
+/// line's own colors everywhere else. SGR sequences inside a span get `style`
+/// put back after them, and the colors in effect are restored when it ends.
+pub fn overlay(line: &str, spans: &[Range<usize>], style: &str) -> String {
+    let bytes = line.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len() + spans.len() * 16);
+    // SGR sequences since the last reset, to restore after a span.
+    let mut active = Sgr::default();
+    let mut spans = spans.iter().filter(|s| !s.is_empty()).peekable();
+    let mut inside = false;
+    let mut plain = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        if let Some(len) = escape_len(&bytes[i..]) {
+            let seq = &bytes[i..i + len];
+            out.extend_from_slice(seq);
+            if active.apply(&String::from_utf8_lossy(seq)) && inside {
+                out.extend_from_slice(style.as_bytes());
+            }
+            i += len;
+            continue;
+        }
+        if !inside && spans.peek().is_some_and(|s| plain == s.start) {
+            out.extend_from_slice(style.as_bytes());
+            inside = true;
+        }
+        out.push(bytes[i]);
+        plain += 1;
+        i += 1;
+        if inside && spans.peek().is_some_and(|s| plain == s.end) {
+            out.extend_from_slice(RESET.as_bytes());
+            out.extend_from_slice(active.prefix().as_bytes());
+            inside = false;
+            spans.next();
+        }
+    }
+    if inside {
+        out.extend_from_slice(RESET.as_bytes());
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SgrKind {
+    Foreground,
+    Background,
+    Other,
+}
+
+/// The SGR sequences in effect since the last reset. A sequence that only
+/// sets a color replaces the one that set it before, so the colors carried
+/// over to a cut-off piece stay short.
+#[derive(Debug, Default)]
+struct Sgr {
+    seqs: Vec<(SgrKind, String)>,
+}
+
+impl Sgr {
+    /// Takes `seq` into account; false if it isn't an SGR sequence.
+    fn apply(&mut self, seq: &str) -> bool {
+        let Some(params) = seq.strip_prefix("\x1b[").and_then(|p| p.strip_suffix('m')) else {
+            return false;
+        };
+        if matches!(params, "" | "0") {
+            self.seqs.clear();
+            return true;
+        }
+        let kind = sgr_kind(params);
+        if kind != SgrKind::Other {
+            self.seqs.retain(|(k, _)| *k != kind);
+        }
+        self.seqs.push((kind, seq.to_string()));
+        true
+    }
+
+    fn prefix(&self) -> String {
+        self.seqs.iter().map(|(_, seq)| seq.as_str()).collect()
+    }
+}
+
+/// Whether the SGR parameters `params` do nothing but set one color.
+fn sgr_kind(params: &str) -> SgrKind {
+    let parts: Vec<&str> = params.split([';', ':']).collect();
+    let extended = matches!(parts.as_slice(), [_, "5", _] | [_, "2", _, _, _]);
+    match parts[0].parse::<u16>() {
+        Ok(30..=37 | 39 | 90..=97) if parts.len() == 1 => SgrKind::Foreground,
+        Ok(40..=47 | 49 | 100..=107) if parts.len() == 1 => SgrKind::Background,
+        Ok(38) if extended => SgrKind::Foreground,
+        Ok(48) if extended => SgrKind::Background,
+        _ => SgrKind::Other,
+    }
+}
+
+/// Columns `c` takes on screen, drawn as `shown` draws it.
+fn char_width(c: char) -> usize {
+    match c {
+        c if c.is_ascii_control() => 2,
+        c if c.is_control() => 1,
+        c => c.width().unwrap_or(0),
+    }
+}
+
+/// `c` as it is drawn. Control characters would move the cursor, so they
+/// become `^X` like `cat -v` shows them, or U+FFFD outside ASCII.
+fn shown(c: char, buf: &mut [u8; 4]) -> &str {
+    match c {
+        c if c.is_ascii_control() => {
+            buf[0] = b'^';
+            buf[1] = c as u8 ^ 0x40;
+            std::str::from_utf8(&buf[..2]).unwrap_or("^?")
+        }
+        c if c.is_control() => char::REPLACEMENT_CHARACTER.encode_utf8(buf),
+        c => c.encode_utf8(buf),
+    }
+}
+
+/// Display width of `line`, with tabs expanded.
+pub fn width(line: &str) -> usize {
+    let mut col = 0;
+    let mut rest = line;
+    while let Some(c) = rest.chars().next() {
+        if c == '\x1b' {
+            rest = &rest[escape_end(rest)..];
+            continue;
+        }
+        col += match c {
+            '\t' => TAB_STOP - col % TAB_STOP,
+            c => char_width(c),
+        };
+        rest = &rest[c.len_utf8()..];
+    }
+    col
+}
+
+/// Byte length of the escape sequence `s` starts with, up to a character
+/// boundary.
+fn escape_end(s: &str) -> usize {
+    let mut len = escape_len(s.as_bytes()).unwrap_or(1);
+    while !s.is_char_boundary(len) {
+        len += 1;
+    }
+    len
+}
+
+/// The pieces `line` is laid out in, `width` columns each, starting at
+/// column `start`: at most `rows` of them, each opening with the colors in
+/// effect where it begins. Tabs become spaces, control characters `^X`, and a
+/// wide character that doesn't fit at the end of a piece moves on to the next
+/// one.
+pub fn cut(line: &str, start: usize, width: usize, rows: usize) -> Vec<String> {
+    let mut pieces = Pieces::new(Some(Vec::new()));
+    layout(line, start, width, rows, &mut pieces);
+    pieces.out.unwrap_or_default()
+}
+
+/// Screen rows `line` takes when wrapped at `width` columns.
+pub fn rows(line: &str, width: usize) -> usize {
+    let mut pieces = Pieces::new(None);
+    layout(line, 0, width, usize::MAX, &mut pieces);
+    pieces.count
+}
+
+/// Collects the pieces of `layout`, or with no `out`, only counts them.
+struct Pieces {
+    out: Option<Vec<String>>,
+    piece: String,
+    begun: bool,
+    count: usize,
+}
+
+impl Pieces {
+    fn new(out: Option<Vec<String>>) -> Pieces {
+        Pieces {
+            out,
+            piece: String::new(),
+            begun: false,
+            count: 0,
+        }
+    }
+
+    fn text(&mut self, text: &str, active: &Sgr) {
+        if self.out.is_none() {
+            return;
+        }
+        if !self.begun {
+            self.piece.push_str(&active.prefix());
+            self.begun = true;
+        }
+        self.piece.push_str(text);
+    }
+
+    fn escape(&mut self, seq: &str) {
+        if self.begun {
+            self.piece.push_str(seq);
+        }
+    }
+
+    fn finish(&mut self) {
+        self.count += 1;
+        self.begun = false;
+        if let Some(out) = &mut self.out {
+            let mut piece = std::mem::take(&mut self.piece);
+            if piece.contains('\x1b') {
+                piece.push_str(RESET);
+            }
+            out.push(piece);
+        }
+    }
+}
+
+fn layout(line: &str, start: usize, width: usize, rows: usize, pieces: &mut Pieces) {
+    let width = width.max(1);
+    // SGR sequences since the last reset, to open each piece with.
+    let mut active = Sgr::default();
+    let mut piece_start = start;
+    let mut col = 0;
+    let mut rest = line;
+    let mut buf = [0; 4];
+    while let Some(c) = rest.chars().next() {
+        if c == '\x1b' {
+            let len = escape_end(rest);
+            let seq = &rest[..len];
+            active.apply(seq);
+            pieces.escape(seq);
+            rest = &rest[len..];
+            continue;
+        }
+        rest = &rest[c.len_utf8()..];
+        let (text, w, repeat) = match c {
+            '\t' => (" ", 1, TAB_STOP - col % TAB_STOP),
+            c => (shown(c, &mut buf), char_width(c), 1),
+        };
+        for _ in 0..repeat {
+            if w == 0 {
+                if col >= piece_start {
+                    pieces.text(text, &active);
+                }
+                continue;
+            }
+            if col + w <= piece_start {
+                col += w;
+                continue;
+            }
+            if col < piece_start {
+                // A wide character cut in half by the left edge.
+                pieces.text(&" ".repeat(col + w - piece_start), &active);
+                col += w;
+                continue;
+            }
+            if col + w > piece_start + width {
+                pieces.finish();
+                if pieces.count == rows {
+                    return;
+                }
+                piece_start += width;
+                col = col.max(piece_start);
+            }
+            pieces.text(text, &active);
+            col += w;
+        }
+    }
+    if pieces.count < rows {
+        pieces.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn control_characters_are_shown_in_caret_form() {
+        assert_eq!(cut("a\rb\x08c\x7f", 0, 20, 1), vec!["a^Mb^Hc^?"]);
+        assert_eq!(width("a\rb"), 4);
+        assert_eq!(cut("\u{9b}x", 0, 20, 1), vec!["\u{fffd}x"]);
+        // Two columns wide, so the caret form moves to the next row whole.
+        assert_eq!(cut("ab\r", 0, 3, 2), vec!["ab", "^M"]);
+        assert_eq!(rows("ab\r", 3), 2);
+    }
+
+    #[test]
+    fn a_wide_character_that_does_not_fit_moves_to_the_next_piece() {
+        assert_eq!(cut("ab中d", 0, 3, 3), vec!["ab", "中d"]);
+        assert_eq!(rows("ab中d", 3), 2);
+        assert_eq!(width("ab中d"), 5);
+        // Cut in half by the left edge, the visible half is blank.
+        assert_eq!(cut("中b", 1, 5, 1), vec![" b"]);
+    }
+
+    #[test]
+    fn tabs_expand_from_the_line_start_not_the_offset() {
+        assert_eq!(cut("a\tb", 2, 10, 1), vec!["      b"]);
+        assert_eq!(cut("a\tb", 0, 4, 3), vec!["a   ", "    ", "b"]);
+        assert_eq!(width("\x1b[1mab\tc"), 9);
+    }
+
+    #[test]
+    fn each_piece_opens_with_the_colors_in_effect() {
+        let pieces = cut("\x1b[31mabc\x1b[1mdef\x1b[0mgh", 0, 3, 3);
+        assert_eq!(pieces[0], "\x1b[31mabc\x1b[1m\x1b[0m");
+        assert_eq!(pieces[1], "\x1b[31m\x1b[1mdef\x1b[0m\x1b[0m");
+        assert_eq!(pieces[2], "gh");
+        // A later color replaces the earlier one instead of piling up.
+        let pieces = cut("\x1b[31mab\x1b[32mcd", 0, 2, 2);
+        assert_eq!(pieces[1], "\x1b[32mcd\x1b[0m");
+    }
+
+    #[test]
+    fn at_most_rows_pieces_are_cut() {
+        assert_eq!(cut("abcdefg", 0, 2, 2), vec!["ab", "cd"]);
+        assert_eq!(rows("abcdefg", 2), 4);
+        assert_eq!(rows("", 5), 1);
+    }
+
+    #[test]
+    fn strip_drops_escape_sequences() {
+        assert_eq!(strip("\x1b[1;31mred\x1b[0m!"), "red!");
+        assert_eq!(strip("a\x1b=b"), "ab");
+    }
+
+    fn highlight(line: &str, span: Range<usize>) -> String {
+        overlay(line, std::slice::from_ref(&span), "\x1b[7m")
+    }
+
+    #[test]
+    fn skip_text_keeps_the_escapes_of_the_skipped_text() {
+        assert_eq!(skip_text(b"\x1b[31mabc\x1b[0m", 2), b"\x1b[31mc\x1b[0m");
+        assert_eq!(skip_text(b"ab", 5), b"");
+    }
+
+    #[test]
+    fn overlay_restores_the_colors_after_a_span() {
+        let line = "\x1b[32mabcd\x1b[0m";
+        assert_eq!(
+            highlight(line, 1..2),
+            "\x1b[32ma\x1b[7mb\x1b[0m\x1b[32mcd\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn overlay_puts_the_style_back_after_a_reset_inside_a_span() {
+        let line = "\x1b[32mab\x1b[0mcd";
+        assert_eq!(
+            highlight(line, 1..3),
+            "\x1b[32ma\x1b[7mb\x1b[0m\x1b[7mc\x1b[0md"
+        );
+        // An unterminated span is closed at the end of the line.
+        assert_eq!(highlight("abc", 2..9), "ab\x1b[7mc\x1b[0m");
+    }
+}
@@ -0,0 +1,129 @@
+use crate::lua;
+use crate::nvim::{self, NvimInfo};
+use crate::util;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::SystemTime;
+use xxhash_rust::xxh3::{Xxh3, xxh3_64};
+
+const CACHE_VERSION: &str = "1";
+const MAX_CACHE_BYTES: u64 = 128 * 1024 * 1024; // 128MB
+
+/// `$XDG_CACHE_HOME/meow`, falling back to `~/.cache/meow`.
+pub fn cache_root() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CACHE_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".cache")))?;
+    Some(base.join("meow"))
+}
+
+/// ANSI output of the generator, stored under a hash of the file content and
+/// everything else that can change how it is colored.
+pub struct Cache {
+    dir: PathBuf,
+    salt: String,
+    /// Whether this run added entries, so the size limit needs checking.
+    stored: AtomicBool,
+}
+
+impl Cache {
+    pub fn open(info: &NvimInfo) -> Option<Cache> {
+        let dir = cache_root()?.join("highlight");
+        fs::create_dir_all(&dir).ok()?;
+        let salt = format!(
+            "{}\0{}\0{}\0{:x}",
+            CACHE_VERSION,
+            info.theme,
+            util::nvim_fingerprint(nvim::binary()),
+            xxh3_64(lua::LUA_GENERATOR.as_bytes())
+        );
+        Some(Cache {
+            dir,
+            salt,
+            stored: AtomicBool::new(false),
+        })
+    }
+
+    /// `filetype` is the forced filetype if any, otherwise whatever the
+    /// detection depends on (extension or basename).
+    pub fn key(&self, content: &[u8], filetype: &str) -> String {
+        let mut hasher = Xxh3::new();
+        hasher.update(self.salt.as_bytes());
+        hasher.update(b"\0");
+        hasher.update(filetype.as_bytes());
+        hasher.update(b"\0");
+        hasher.update(content);
+        format!("{:032x}", hasher.digest128())
+    }
+
+    pub fn lookup(&self, key: &str) -> Option<Vec<u8>> {
+        let path = self.dir.join(key);
+        let data = fs::read(&path).ok()?;
+        if let Ok(f) = File::options().write(true).open(&path) {
+            let _ = f.set_modified(SystemTime::now());
+        }
+        Some(data)
+    }
+
+    pub fn store(&self, key: &str, data: &[u8]) {
+        let tmp = self
+            .dir
+            .join(format!(".{}.{}.tmp", key, std::process::id()));
+        let written = File::create(&tmp).and_then(|mut f| f.write_all(data));
+        if written.is_err() || fs::rename(&tmp, self.dir.join(key)).is_err() {
+            let _ = fs::remove_file(&tmp);
+            return;
+        }
+        self.stored.store(true, Ordering::Relaxed);
+    }
+
+    /// Brings the cache back under its size limit if this run stored
+    /// anything. Done once at exit, since it stats every entry.
+    pub fn trim(&self) {
+        if self.stored.load(Ordering::Relaxed) {
+            let _ = evict(&self.dir, MAX_CACHE_BYTES);
+        }
+    }
+}
+
+/// Drops the least recently used entries until the directory fits in `max`.
+fn evict(dir: &Path, max: u64) -> io::Result<()> {
+    let mut entries = Vec::new();
+    let mut total = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        if !meta.is_file() {
+            continue;
+        }
+        total += meta.len();
+        let mtime = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+        entries.push((mtime, meta.len(), entry.path()));
+    }
+    if total <= max {
+        return Ok(());
+    }
+    entries.sort_by_key(|e| e.0);
+    for (_, len, path) in entries {
+        if total <= max {
+            break;
+        }
+        if fs::remove_file(&path).is_ok() {
+            total -= len;
+        }
+    }
+    Ok(())
+}
+
+pub fn clear() -> io::Result<()> {
+    let Some(root) = cache_root() else {
+        return Ok(());
+    };
+    match fs::remove_dir_all(root.join("highlight")) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,340 @@
+use std::io::{self, Read, Write};
+
+/// GNU cat's line formatting flags.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CatOpts {
+    pub number: bool,
+    pub number_nonblank: bool,
+    pub squeeze_blank: bool,
+    pub show_ends: bool,
+    pub show_tabs: bool,
+    pub show_nonprinting: bool,
+}
+
+impl CatOpts {
+    pub fn is_active(&self) -> bool {
+        self.number
+            || self.number_nonblank
+            || self.squeeze_blank
+            || self.show_ends
+            || self.show_tabs
+            || self.show_nonprinting
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Escape {
+    None,
+    Esc,
+    Csi,
+}
+
+/// Applies `CatOpts` to a byte stream. State carries over from one input to
+/// the next, so numbering and squeezing continue across files like cat's.
+///
+/// Highlighted output is written with `markup` set: its CSI sequences are
+/// passed through untouched and never count as line content, so markers and
+/// numbers land outside the color codes.
+pub struct CatFilter {
+    opts: CatOpts,
+    line: u64,
+    at_line_start: bool,
+    blank_run: usize,
+    pending_cr: bool,
+    escape: Escape,
+    /// Markup seen at the start of a line, held back until we know whether the
+    /// line gets a number (which has to come first) or is squeezed.
+    held: Vec<u8>,
+}
+
+impl CatFilter {
+    pub fn new(opts: CatOpts) -> CatFilter {
+        CatFilter {
+            opts,
+            line: 0,
+            at_line_start: true,
+            blank_run: 0,
+            pending_cr: false,
+            escape: Escape::None,
+            held: Vec::new(),
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.opts.is_active()
+    }
+
+    pub fn write(&mut self, out: &mut impl Write, data: &[u8], markup: bool) -> io::Result<()> {
+        if !self.is_active() {
+            return out.write_all(data);
+        }
+        let mut buf = Vec::with_capacity(data.len() + data.len() / 8);
+        for &b in data {
+            self.push(&mut buf, b, markup);
+        }
+        out.write_all(&buf)
+    }
+
+    pub fn copy(
+        &mut self,
+        reader: &mut impl Read,
+        out: &mut impl Write,
+        markup: bool,
+    ) -> io::Result<()> {
+        if !self.is_active() {
+            io::copy(reader, out)?;
+            return Ok(());
+        }
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            let n = match reader.read(&mut buf) {
+                Ok(0) => return Ok(()),
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            self.write(out, &buf[..n], markup)?;
+        }
+    }
+
+    /// Flushes anything held back at the end of one input.
+    pub fn end_input(&mut self, out: &mut impl Write) -> io::Result<()> {
+        let mut buf = std::mem::take(&mut self.held);
+        if self.escape == Escape::Esc {
+            self.content(&mut buf, 0x1b);
+        }
+        self.escape = Escape::None;
+        if self.pending_cr {
+            self.pending_cr = false;
+            self.content(&mut buf, b'\r');
+        }
+        out.write_all(&buf)
+    }
+
+    fn push(&mut self, buf: &mut Vec<u8>, b: u8, markup: bool) {
+        if markup {
+            match self.escape {
+                Escape::Csi => {
+                    self.markup(buf, b);
+                    if (0x40..=0x7e).contains(&b) {
+                        self.escape = Escape::None;
+                    }
+                    return;
+                }
+                Escape::Esc => {
+                    self.escape = Escape::None;
+                    if b == b'[' {
+                        self.markup(buf, 0x1b);
+                        self.markup(buf, b);
+                        self.escape = Escape::Csi;
+                        return;
+                    }
+                    self.visible(buf, 0x1b);
+                }
+                Escape::None => {}
+            }
+            if b == 0x1b {
+                self.escape = Escape::Esc;
+                return;
+            }
+        }
+        self.visible(buf, b);
+    }
+
+    fn markup(&mut self, buf: &mut Vec<u8>, b: u8) {
+        if self.at_line_start {
+            self.held.push(b);
+        } else {
+            buf.push(b);
+        }
+    }
+
+    fn visible(&mut self, buf: &mut Vec<u8>, b: u8) {
+        if self.pending_cr {
+            self.pending_cr = false;
+            if b == b'\n' {
+                buf.extend_from_slice(b"^M");
+            } else {
+                self.content(buf, b'\r');
+            }
+        }
+
+        if self.at_line_start {
+            if b == b'\n' {
+                self.blank_run += 1;
+                if self.opts.squeeze_blank && self.blank_run > 1 {
+                    self.held.clear();
+                    return;
+                }
+                if self.opts.number && !self.opts.number_nonblank {
+                    self.write_number(buf);
+                }
+            } else {
+                self.blank_run = 0;
+                if self.opts.number || self.opts.number_nonblank {
+                    self.write_number(buf);
+                }
+                self.at_line_start = false;
+            }
+            buf.append(&mut self.held);
+        }
+
+        match b {
+            b'\n' => {
+                if self.opts.show_ends {
+                    buf.push(b'$');
+                }
+                buf.push(b'\n');
+                self.at_line_start = true;
+            }
+            // GNU cat shows the CR of a CRLF ending when marking line ends.
+            b'\r' if self.opts.show_ends && !self.opts.show_nonprinting => self.pending_cr = true,
+            _ => self.content(buf, b),
+        }
+    }
+
+    fn write_number(&mut self, buf: &mut Vec<u8>) {
+        self.line += 1;
+        buf.extend_from_slice(format!("{:>6}\t", self.line).as_bytes());
+    }
+
+    fn content(&self, buf: &mut Vec<u8>, b: u8) {
+        if b == b'\t' {
+            if self.opts.show_tabs {
+                buf.extend_from_slice(b"^I");
+            } else {
+                buf.push(b);
+            }
+            return;
+        }
+        if !self.opts.show_nonprinting {
+            buf.push(b);
+            return;
+        }
+        let low = if b >= 0x80 {
+            buf.extend_from_slice(b"M-");
+            b - 0x80
+        } else {
+            b
+        };
+        match low {
+            0x7f => buf.extend_from_slice(b"^?"),
+            c if c < 0x20 => {
+                buf.push(b'^');
+                buf.push(c + 0x40);
+            }
+            c => buf.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs `chunks` through a filter as highlighted output, one write each.
+    fn highlighted(opts: CatOpts, chunks: &[&str]) -> String {
+        let mut cat = CatFilter::new(opts);
+        let mut out = Vec::new();
+        for chunk in chunks {
+            cat.write(&mut out, chunk.as_bytes(), true).unwrap();
+        }
+        cat.end_input(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    const NUMBER: CatOpts = CatOpts {
+        number: true,
+        number_nonblank: false,
+        squeeze_blank: false,
+        show_ends: false,
+        show_tabs: false,
+        show_nonprinting: false,
+    };
+
+    #[test]
+    fn number_goes_before_leading_color() {
+        let out = highlighted(NUMBER, &["\x1b[31mfn\x1b[0m main\n\n\x1b[0m\n"]);
+        assert_eq!(
+            out,
+            "     1\t\x1b[31mfn\x1b[0m main\n     2\t\n     3\t\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn escape_split_across_writes() {
+        let out = highlighted(NUMBER, &["\x1b", "[3", "1mfn\x1b[0m\n"]);
+        assert_eq!(out, "     1\t\x1b[31mfn\x1b[0m\n");
+    }
+
+    #[test]
+    fn number_nonblank_skips_lines_with_only_markup() {
+        let opts = CatOpts {
+            number_nonblank: true,
+            ..CatOpts::default()
+        };
+        let out = highlighted(opts, &["\x1b[31ma\x1b[0m\n\n\x1b[0m\n\x1b[32mb\x1b[0m\n"]);
+        assert_eq!(
+            out,
+            "     1\t\x1b[31ma\x1b[0m\n\n\x1b[0m\n     2\t\x1b[32mb\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn squeeze_drops_markup_of_squeezed_lines() {
+        let opts = CatOpts {
+            squeeze_blank: true,
+            ..CatOpts::default()
+        };
+        let out = highlighted(opts, &["\x1b[31ma\x1b[0m\n\n\x1b[0m\n\n\x1b[32mb\x1b[0m\n"]);
+        assert_eq!(out, "\x1b[31ma\x1b[0m\n\n\x1b[32mb\x1b[0m\n");
+    }
+
+    #[test]
+    fn show_ends_after_trailing_reset() {
+        let opts = CatOpts {
+            show_ends: true,
+            ..CatOpts::default()
+        };
+        let out = highlighted(opts, &["\x1b[31ma\x1b[0m\n\n"]);
+        assert_eq!(out, "\x1b[31ma\x1b[0m$\n$\n");
+    }
+
+    #[test]
+    fn show_all_leaves_markup_alone() {
+        let opts = CatOpts {
+            show_ends: true,
+            show_tabs: true,
+            show_nonprinting: true,
+            ..CatOpts::default()
+        };
+        let out = highlighted(opts, &["\x1b[31m\ta\x01\x1b[0m\r\n"]);
+        assert_eq!(out, "\x1b[31m^Ia^A\x1b[0m^M$\n");
+    }
+
+    #[test]
+    fn escapes_are_content_without_markup() {
+        let opts = CatOpts {
+            show_nonprinting: true,
+            ..CatOpts::default()
+        };
+        let mut cat = CatFilter::new(opts);
+        let mut out = Vec::new();
+        cat.write(&mut out, b"\x1b[31ma\n", false).unwrap();
+        assert_eq!(out, b"^[[31ma\n");
+    }
+
+    #[test]
+    fn numbering_continues_across_inputs() {
+        let mut cat = CatFilter::new(NUMBER);
+        let mut out = Vec::new();
+        for input in ["\x1b[31ma\x1b[0m\n", "\x1b[32mb\x1b[0m\n"] {
+            cat.write(&mut out, input.as_bytes(), true).unwrap();
+            cat.end_input(&mut out).unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "     1\t\x1b[31ma\x1b[0m\n     2\t\x1b[32mb\x1b[0m\n"
+        );
+    }
+}
@@ -0,0 +1,59 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum MeowError {
+    NotFound,
+    PermissionDenied,
+    /// The configured Neovim binary does not exist.
+    NvimMissing(PathBuf),
+    /// Neovim went away mid-request; `stderr` holds the tail of what it printed.
+    NvimCrashed {
+        stderr: String,
+    },
+    /// Neovim answered the request with an error (usually a Lua error).
+    NvimError(String),
+    Timeout,
+    BrokenPipe,
+    Io(io::Error),
+}
+
+impl MeowError {
+    /// Neovim's stderr, if this error carries any.
+    pub fn stderr_tail(&self) -> Option<&str> {
+        match self {
+            MeowError::NvimCrashed { stderr } if !stderr.trim().is_empty() => Some(stderr),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for MeowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MeowError::NotFound => write!(f, "No such file or directory"),
+            MeowError::PermissionDenied => write!(f, "Permission denied"),
+            MeowError::NvimMissing(bin) => write!(f, "{} not found", bin.display()),
+            MeowError::NvimCrashed { .. } => write!(f, "Neovim exited unexpectedly"),
+            MeowError::NvimError(msg) => write!(f, "Neovim error: {}", msg),
+            MeowError::Timeout => write!(f, "Neovim timed out"),
+            MeowError::BrokenPipe => write!(f, "Broken pipe"),
+            MeowError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for MeowError {}
+
+impl From<io::Error> for MeowError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::NotFound => MeowError::NotFound,
+            io::ErrorKind::PermissionDenied => MeowError::PermissionDenied,
+            io::ErrorKind::BrokenPipe => MeowError::BrokenPipe,
+            io::ErrorKind::TimedOut => MeowError::Timeout,
+            _ => MeowError::Io(e),
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+/// How often a followed file is checked for new bytes.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+/// Most lines highlighted at once, so a burst shows up in steps.
+const BATCH_LINES: usize = 200;
+/// Lines before a batch handed to the highlighter along with it.
+const CONTEXT_LINES: usize = 20;
+
+/// New lines of a followed file, with some of the ones before as context.
+pub struct Batch {
+    pub content: Vec<u8>,
+    pub context: usize,
+    /// 0-based index in the file of the first new line.
+    pub first_line: usize,
+    /// Bytes at the start of the first new line that were already shown,
+    /// when the file ended without a newline.
+    pub shown: usize,
+}
+
+pub enum Appended {
+    Lines(Batch),
+    /// The file got shorter than what was read of it, as when a log is
+    /// rotated by truncation.
+    Truncated,
+}
+
+/// Reads what gets appended to a file after a given offset, a whole line at
+/// a time, like `tail -f`.
+pub struct Tail {
+    file: File,
+    offset: u64,
+    /// The start of a line whose newline hasn't been written yet.
+    partial: Vec<u8>,
+    /// How much of `partial` was shown before following began.
+    shown: usize,
+    recent: VecDeque<Vec<u8>>,
+    next_line: usize,
+}
+
+impl Tail {
+    /// Follows `path` from `offset` bytes in, where everything before has
+    /// already been shown.
+    pub fn open(path: &Path, offset: u64) -> io::Result<Tail> {
+        let file = File::open(path)?;
+        // Counted for the line numbers, keeping the last lines as context.
+        let mut recent = VecDeque::new();
+        let mut next_line = 0;
+        let mut reader = BufReader::new((&file).take(offset));
+        let mut line = Vec::new();
+        while reader.read_until(b'\n', &mut line)? > 0 {
+            if !line.ends_with(b"\n") {
+                break;
+            }
+            next_line += 1;
+            remember(&mut recent, std::mem::take(&mut line));
+        }
+        drop(reader);
+        // An unfinished last line is completed by what gets appended.
+        Ok(Tail {
+            file,
+            offset,
+            shown: line.len(),
+            partial: line,
+            recent,
+            next_line,
+        })
+    }
+
+    /// Whether the file ended partway through a line, which what gets
+    /// appended finishes.
+    pub fn mid_line(&self) -> bool {
+        self.shown > 0
+    }
+
+    /// Waits until whole lines are appended, and returns up to
+    /// `BATCH_LINES` of them.
+    pub fn wait(&mut self) -> io::Result<Appended> {
+        loop {
+            let len = self.file.metadata()?.len();
+            if len < self.offset {
+                return Ok(Appended::Truncated);
+            }
+            if len > self.offset {
+                self.file.seek(SeekFrom::Start(self.offset))?;
+                let mut reader = BufReader::new((&self.file).take(len - self.offset));
+                let mut lines = Vec::new();
+                let mut line = std::mem::take(&mut self.partial);
+                while lines.len() < BATCH_LINES {
+                    let read = reader.read_until(b'\n', &mut line)?;
+                    if read == 0 {
+                        break;
+                    }
+                    self.offset += read as u64;
+                    if line.ends_with(b"\n") {
+                        lines.push(std::mem::take(&mut line));
+                    }
+                }
+                self.partial = line;
+                drop(reader);
+                if !lines.is_empty() {
+                    return Ok(Appended::Lines(self.batch(lines)));
+                }
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    fn batch(&mut self, lines: Vec<Vec<u8>>) -> Batch {
+        let mut content: Vec<u8> = self.recent.iter().flatten().copied().collect();
+        let context = self.recent.len();
+        let first_line = self.next_line;
+        self.next_line += lines.len();
+        for line in lines {
+            content.extend_from_slice(&line);
+            remember(&mut self.recent, line);
+        }
+        Batch {
+            content,
+            context,
+            first_line,
+            shown: std::mem::take(&mut self.shown),
+        }
+    }
+}
+
+fn remember(recent: &mut VecDeque<Vec<u8>>, line: Vec<u8>) {
+    if recent.len() == CONTEXT_LINES {
+        recent.pop_front();
+    }
+    recent.push_back(line);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    fn scratch(name: &str, content: &[u8]) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("meow-test-follow-{}-{}", name, std::process::id()));
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    fn append(path: &Path, data: &[u8]) {
+        let mut f = OpenOptions::new().append(true).open(path).unwrap();
+        f.write_all(data).unwrap();
+    }
+
+    fn next_batch(tail: &mut Tail) -> Batch {
+        match tail.wait().unwrap() {
+            Appended::Lines(batch) => batch,
+            Appended::Truncated => panic!("unexpected truncation"),
+        }
+    }
+
+    #[test]
+    fn appends_after_an_unfinished_line() {
+        let path = scratch("partial", b"a\nb");
+        let mut tail = Tail::open(&path, 3).unwrap();
+
+        append(&path, b"c\nd\n");
+        let batch = next_batch(&mut tail);
+        assert_eq!(batch.content, b"a\nbc\nd\n");
+        assert_eq!(batch.context, 1);
+        assert_eq!(batch.first_line, 1);
+        assert_eq!(batch.shown, 1);
+
+        append(&path, b"e\n");
+        let batch = next_batch(&mut tail);
+        assert_eq!(batch.content, b"a\nbc\nd\ne\n");
+        assert_eq!(batch.context, 3);
+        assert_eq!(batch.first_line, 3);
+        assert_eq!(batch.shown, 0);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn waits_for_the_newline_of_appended_lines() {
+        let path = scratch("pieces", b"a\n");
+        let mut tail = Tail::open(&path, 2).unwrap();
+        append(&path, b"b");
+        let writer = {
+            let path = path.clone();
+            thread::spawn(move || {
+                thread::sleep(POLL_INTERVAL * 2);
+                append(&path, b"c\n");
+            })
+        };
+        let batch = next_batch(&mut tail);
+        writer.join().unwrap();
+        assert_eq!(batch.content, b"a\nbc\n");
+        assert_eq!(batch.first_line, 1);
+        assert_eq!(batch.shown, 0);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn notices_truncation() {
+        let path = scratch("truncate", b"a\nb\n");
+        let mut tail = Tail::open(&path, 4).unwrap();
+        fs::write(&path, b"c\n").unwrap();
+        assert!(matches!(tail.wait().unwrap(), Appended::Truncated));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,144 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Bytes shown on each row of a dump.
+pub const ROW_BYTES: usize = 16;
+
+const RESET: &str = "\x1b[0m";
+const OFFSET_COLOR: &str = "\x1b[90m";
+
+/// Color of a byte by its class: NUL, whitespace, printable ASCII, other
+/// ASCII, and everything else.
+fn class_color(b: u8) -> &'static str {
+    match b {
+        0 => "\x1b[90m",
+        b if b.is_ascii_whitespace() => "\x1b[32m",
+        b if b.is_ascii_graphic() => "\x1b[36m",
+        b if b.is_ascii() => "\x1b[35m",
+        _ => "\x1b[33m",
+    }
+}
+
+/// Writes `text` in the color of byte `b`, switching colors only when the
+/// class changes.
+fn paint(out: &mut String, current: &mut Option<&'static str>, b: u8, text: &str) {
+    let color = class_color(b);
+    if *current != Some(color) {
+        out.push_str(color);
+        *current = Some(color);
+    }
+    out.push_str(text);
+}
+
+/// One row of a dump: `bytes` in hex, in two groups of eight, then as text
+/// with a `.` for anything unprintable, colored by byte class if `color`.
+pub fn row(bytes: &[u8], color: bool) -> String {
+    let mut out = String::with_capacity(ROW_BYTES * 12);
+    let mut current = None;
+    for i in 0..ROW_BYTES {
+        if i == ROW_BYTES / 2 {
+            out.push(' ');
+        }
+        match bytes.get(i) {
+            Some(&b) if color => paint(&mut out, &mut current, b, &format!("{:02x} ", b)),
+            Some(&b) => out.push_str(&format!("{:02x} ", b)),
+            None => out.push_str("   "),
+        }
+    }
+    if current.take().is_some() {
+        out.push_str(RESET);
+    }
+    out.push('│');
+    for &b in bytes {
+        let c = if b.is_ascii_graphic() || b == b' ' {
+            b as char
+        } else {
+            '.'
+        };
+        if color {
+            paint(&mut out, &mut current, b, c.encode_utf8(&mut [0; 4]));
+        } else {
+            out.push(c);
+        }
+    }
+    if current.is_some() {
+        out.push_str(RESET);
+    }
+    out.push_str(&" ".repeat(ROW_BYTES - bytes.len()));
+    out.push('│');
+    out
+}
+
+/// Writes a dump of everything `reader` yields, each row after its offset.
+pub fn dump(reader: &mut impl Read, out: &mut impl Write, color: bool) -> io::Result<()> {
+    let mut buf = [0; ROW_BYTES];
+    let mut offset = 0;
+    loop {
+        let n = fill(reader, &mut buf)?;
+        if n == 0 {
+            return Ok(());
+        }
+        let bytes = row(&buf[..n], color);
+        if color {
+            writeln!(out, "{}{:08x}:{} {}", OFFSET_COLOR, offset, RESET, bytes)?;
+        } else {
+            writeln!(out, "{:08x}: {}", offset, bytes)?;
+        }
+        offset += n;
+        if n < ROW_BYTES {
+            return Ok(());
+        }
+    }
+}
+
+/// Reads until `buf` is full or the input ends, so a pipe that delivers a
+/// few bytes at a time still makes whole rows.
+fn fill(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match reader.read(&mut buf[n..]) {
+            Ok(0) => break,
+            Ok(read) => n += read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(n)
+}
+
+/// A file shown as a dump, read from disk a row at a time as rows come into
+/// view, so a file of any size opens at once.
+pub struct HexFile {
+    file: File,
+    len: u64,
+}
+
+impl HexFile {
+    pub fn open(path: &Path) -> io::Result<HexFile> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        Ok(HexFile { file, len })
+    }
+
+    pub fn rows(&self) -> usize {
+        self.len.div_ceil(ROW_BYTES as u64) as usize
+    }
+
+    /// Hex digits the largest offset takes, at least eight.
+    pub fn offset_width(&self) -> usize {
+        let digits = (u64::BITS - self.len.leading_zeros()).div_ceil(4) as usize;
+        digits.max(8)
+    }
+
+    /// Row `i`, colored; empty if it can't be read.
+    pub fn row(&self, i: usize) -> String {
+        let mut buf = [0; ROW_BYTES];
+        let mut file = &self.file;
+        let n = file
+            .seek(SeekFrom::Start((i * ROW_BYTES) as u64))
+            .and_then(|_| fill(&mut file, &mut buf))
+            .unwrap_or(0);
+        row(&buf[..n], true)
+    }
+}
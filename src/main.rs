@@ -1,28 +1,49 @@
+mod ansi;
+mod cache;
+mod cat;
+mod error;
+mod follow;
+mod hex;
 mod lua;
+mod nvim;
+mod pager;
+mod range;
+mod screen;
+mod state;
+mod style;
+mod temp;
 mod util;
 
-use clap::{Parser, ValueEnum};
-use crossterm::{
-    cursor,
-    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
-    execute, queue,
-    style::{Attribute, Color, Print, ResetColor, SetAttribute, SetForegroundColor},
-    terminal::{self, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
-};
-use std::collections::BTreeMap;
+use cache::Cache;
+use cat::{CatFilter, CatOpts};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum, error::ErrorKind};
+use error::MeowError;
+use follow::{Appended, Batch, Tail};
+use nvim::{FileOpts, NvimInfo, Pool, ThemeColors};
+use range::LineRange;
+use std::ffi::OsString;
 use std::fs::{self, File};
-use std::io::{self, BufRead, BufReader, IsTerminal, Write};
+use std::io::{self, BufRead, BufReader, IsTerminal, Read, Write};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
-use std::sync::{Arc, OnceLock, mpsc};
+use std::process::ExitCode;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
-use std::time::Duration;
+use style::{Frame, FrameWriter, Style, StyleComponent};
+use temp::TempDir;
 
 #[cfg(unix)]
 use std::os::unix::fs::FileTypeExt;
 
 const CHUNK_THRESHOLD_BYTES: u64 = 50 * 1024; // 50KB
 const MAX_HIGHLIGHT_SIZE: u64 = 1024 * 1024; // 1MB
+const CHUNK_CONTEXT_LINES: usize = 200;
+/// Fewest lines worth a chunk of their own, since each one repeats
+/// `CHUNK_CONTEXT_LINES` of context and may start another Neovim.
+const MIN_CHUNK_LINES: usize = 4 * CHUNK_CONTEXT_LINES;
+const DETECT_HEAD_BYTES: u64 = 8 * 1024;
+const BOUNDARY_SEARCH_LINES: usize = 200;
 
 #[derive(Debug, Clone, ValueEnum, PartialEq)]
 enum PagerMode {
@@ -34,8 +55,12 @@ enum PagerMode {
 
 #[derive(Parser, Debug, Clone)]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true)]
 struct Args {
-    #[arg(required = true)]
+    #[command(subcommand)]
+    command: Option<Cmd>,
+
+    /// Files to print; `-` or no files at all reads standard input
     files: Vec<PathBuf>,
 
     #[arg(long, short = 'f')]
@@ -46,30 +71,198 @@ struct Args {
 
     #[arg(long, short = 'p', default_value = "auto", value_enum)]
     pager: PagerMode,
+
+    /// Name shown for standard input; its extension drives filetype detection
+    #[arg(long, value_name = "NAME")]
+    file_name: Option<String>,
+
+    /// Neovim filetype to use instead of detecting one (e.g. `rust`, `sh`)
+    #[arg(long, short = 'l', visible_alias = "filetype", value_name = "FT")]
+    language: Option<String>,
+
+    /// Force a filetype for paths matching a glob, as `GLOB:FT` (repeatable)
+    #[arg(long, value_name = "GLOB:FT", value_parser = parse_syntax_mapping)]
+    map_syntax: Vec<SyntaxMapping>,
+
+    /// Always run Neovim instead of reusing cached highlights
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Number of Neovim workers, and of chunks a large file is split into
+    #[arg(long, short = 'j', value_name = "N", value_parser = clap::value_parser!(u16).range(1..))]
+    jobs: Option<u16>,
+
+    /// Ask Neovim for the colorscheme and runtimepath again instead of
+    /// trusting the remembered ones
+    #[arg(long)]
+    refresh_theme: bool,
+
+    /// Print the tail of Neovim's stderr when a highlighter fails
+    #[arg(long)]
+    verbose: bool,
+
+    /// Neovim binary to run (default: `$MEOW_NVIM`, then `nvim` from PATH)
+    #[arg(long, value_name = "PATH")]
+    nvim: Option<PathBuf>,
+
+    /// Number all output lines
+    #[arg(long, short = 'n')]
+    number: bool,
+
+    /// Number nonempty output lines, overrides -n
+    #[arg(long, short = 'b')]
+    number_nonblank: bool,
+
+    /// Suppress repeated empty output lines
+    #[arg(long, short = 's')]
+    squeeze_blank: bool,
+
+    /// Display $ at end of each line
+    #[arg(long, short = 'E')]
+    show_ends: bool,
+
+    /// Display TAB characters as ^I
+    #[arg(long, short = 'T')]
+    show_tabs: bool,
+
+    /// Use ^ and M- notation, except for LFD and TAB
+    #[arg(long, short = 'v')]
+    show_nonprinting: bool,
+
+    /// Equivalent to -vET
+    #[arg(long, short = 'A')]
+    show_all: bool,
+
+    /// Decorations to draw: comma-separated numbers, grid, header, rule or
+    /// plain (default: a header when printing several files)
+    #[arg(long, value_enum, value_delimiter = ',', value_name = "COMPONENTS")]
+    style: Vec<StyleComponent>,
+
+    /// Only print lines A to B, as `A:B`, `A:`, `:B`, `A` or `A:+N`; `-N`
+    /// counts from the end (repeatable)
+    #[arg(long, short = 'r', value_name = "A:B", value_parser = range::parse_line_range, allow_hyphen_values = true)]
+    line_range: Vec<LineRange>,
+
+    /// Emphasize line N, or lines A to B, with the theme's CursorLine
+    /// background (repeatable)
+    #[arg(long, short = 'H', value_name = "N|A:B", value_parser = range::parse_line_range, allow_hyphen_values = true)]
+    highlight_line: Vec<LineRange>,
+
+    /// Keep printing lines as they are appended to the file, like `tail -f`;
+    /// in the pager, stick to the bottom (toggle with `F`)
+    #[arg(long)]
+    follow: bool,
+
+    /// Let the pager take the mouse: wheel scrolling, clicking on lines and
+    /// dragging along the status bar (toggle with `M`)
+    #[arg(long)]
+    mouse: bool,
+
+    /// Print a hex dump with the bytes colored by class, like `xxd`; binary
+    /// files get one in the pager without it
+    #[arg(long)]
+    hex: bool,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum Cmd {
+    /// Manage the on-disk highlight cache
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum CacheAction {
+    /// Remove every cached highlight
+    Clear,
 }
 
 #[derive(Debug, Clone)]
-struct NvimInfo {
-    theme: String,
-    rtp: String,
+struct SyntaxMapping {
+    glob: String,
+    filetype: String,
 }
 
-static LUA_SCRIPT_PATH: OnceLock<Arc<PathBuf>> = OnceLock::new();
-static NVIM_INFO: OnceLock<NvimInfo> = OnceLock::new();
+fn parse_syntax_mapping(s: &str) -> Result<SyntaxMapping, String> {
+    match s.rsplit_once(':') {
+        Some((glob, ft)) if !glob.is_empty() && !ft.is_empty() => Ok(SyntaxMapping {
+            glob: glob.to_string(),
+            filetype: ft.to_string(),
+        }),
+        _ => Err(format!("expected GLOB:FT, got `{}`", s)),
+    }
+}
 
-fn get_lua_script() -> Arc<PathBuf> {
-    LUA_SCRIPT_PATH
-        .get_or_init(|| match util::write_temp_lua_script(lua::LUA_GENERATOR) {
-            Ok(p) => Arc::new(p),
-            Err(e) => {
-                eprintln!("meow: failed to write temp script: {}", e);
-                std::process::exit(1);
-            }
+impl Args {
+    fn cat_opts(&self) -> CatOpts {
+        CatOpts {
+            number: self.number,
+            number_nonblank: self.number_nonblank,
+            squeeze_blank: self.squeeze_blank,
+            show_ends: self.show_ends || self.show_all,
+            show_tabs: self.show_tabs || self.show_all,
+            show_nonprinting: self.show_nonprinting || self.show_all,
+        }
+    }
+
+    /// `--language` wins; otherwise the first `--map-syntax` glob that matches.
+    fn filetype_for(&self, name: &str) -> Option<String> {
+        self.language.clone().or_else(|| {
+            self.map_syntax
+                .iter()
+                .find(|m| util::glob_match(&m.glob, name))
+                .map(|m| m.filetype.clone())
         })
-        .clone()
+    }
+}
+
+static NVIM_INFO: OnceLock<NvimInfo> = OnceLock::new();
+static POOL: OnceLock<Pool> = OnceLock::new();
+static CACHE: OnceLock<Option<Cache>> = OnceLock::new();
+static THEME_COLORS: OnceLock<ThemeColors> = OnceLock::new();
+static FALLBACK: OnceLock<String> = OnceLock::new();
+static FALLBACK_REPORTED: AtomicBool = AtomicBool::new(false);
+
+/// Remembers why output went out unhighlighted; only the first reason is
+/// reported, once, by `report_fallback`.
+fn note_fallback(err: &MeowError) {
+    let _ = FALLBACK.set(err.to_string());
 }
 
-fn get_nvim_info(theme_arg: Option<String>) -> NvimInfo {
+/// Whether Neovim turned out missing or hung; later inputs skip it instead of
+/// waiting out another timeout.
+fn nvim_failed() -> bool {
+    FALLBACK.get().is_some()
+}
+
+fn report_fallback() {
+    if let Some(reason) = FALLBACK.get()
+        && !FALLBACK_REPORTED.swap(true, Ordering::SeqCst)
+    {
+        eprintln!("meow: warning: {}; printing without highlighting", reason);
+    }
+}
+
+fn worker_count() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(4)
+}
+
+fn get_pool(info: &NvimInfo, jobs: Option<u16>) -> &'static Pool {
+    POOL.get_or_init(|| {
+        let workers = jobs.map_or_else(worker_count, usize::from);
+        Pool::new(info.clone(), workers)
+    })
+}
+
+fn init_cache(info: &NvimInfo, enabled: bool) {
+    CACHE.get_or_init(|| if enabled { Cache::open(info) } else { None });
+}
+
+fn get_nvim_info(theme_arg: Option<String>, refresh: bool) -> NvimInfo {
     NVIM_INFO
         .get_or_init(|| {
             if let Some(t) = theme_arg {
@@ -82,585 +275,866 @@ fn get_nvim_info(theme_arg: Option<String>) -> NvimInfo {
                     theme: t,
                     rtp: "".to_string(),
                 }
+            } else if let Some(info) = (!refresh).then(state::load).flatten() {
+                info
             } else {
-                get_neovim_info_safe().unwrap_or(NvimInfo {
-                    theme: "habamax".to_string(),
-                    rtp: "".to_string(),
-                })
+                match nvim::probe() {
+                    Ok(probe) => {
+                        let _ = state::store(&probe);
+                        probe.info
+                    }
+                    Err(_) => NvimInfo {
+                        theme: "habamax".to_string(),
+                        rtp: "".to_string(),
+                    },
+                }
             }
         })
         .clone()
 }
 
-fn main() -> io::Result<()> {
-    let args = Args::parse();
-    let is_tty = io::stdout().is_terminal();
-    let multiple_files = args.files.len() > 1;
+/// `meow cache ...` runs the subcommand, unless a file named `cache` exists:
+/// then it is printed like any other file, as `cat cache` would.
+fn parse_args() -> Args {
+    let mut argv: Vec<OsString> = std::env::args_os().collect();
+    if argv.get(1).is_some_and(|a| a == "cache") && Path::new("cache").exists() {
+        argv.remove(1);
+        let mut args = Args::parse_from(argv);
+        args.files.insert(0, PathBuf::from("cache"));
+        return args;
+    }
+    Args::parse_from(argv)
+}
 
-    for (i, file_path) in args.files.iter().enumerate() {
-        let metadata = match fs::metadata(file_path) {
-            Ok(m) => m,
-            Err(e) => {
-                eprintln!(
-                    "meow: could not read metadata for {}: {}",
-                    file_path.display(),
-                    e
-                );
-                continue;
-            }
-        };
+fn main() -> ExitCode {
+    let args = parse_args();
+    let is_tty = io::stdout().is_terminal();
 
-        #[cfg(unix)]
-        let is_device =
-            metadata.file_type().is_char_device() || metadata.file_type().is_block_device();
-        #[cfg(not(unix))]
-        let is_device = false;
-
-        if is_device {
-            if multiple_files {
-                let mut out = io::stdout().lock();
-                if i > 0 {
-                    let _ = out.write_all(b"\n\n");
+    if let Some(Cmd::Cache { action }) = &args.command {
+        match action {
+            CacheAction::Clear => {
+                if let Err(e) = cache::clear().and_then(|_| state::clear()) {
+                    eprintln!("meow: could not clear cache: {}", e);
+                    return ExitCode::FAILURE;
                 }
-                let _ = out.write_all(
-                    format!("\x1b[1;34m:: {} ::\x1b[0m\n", file_path.display()).as_bytes(),
-                );
             }
-            let mut f = File::open(file_path)?;
-            let mut out = io::stdout().lock();
-            io::copy(&mut f, &mut out)?;
-            continue;
         }
+        return ExitCode::SUCCESS;
+    }
 
-        let size = metadata.len();
-        let skip_highlight = size > MAX_HIGHLIGHT_SIZE && !args.force_color;
-        let exceeds_height = util::file_exceeds_terminal_height(file_path).unwrap_or(false);
-
-        let use_pager = match args.pager {
-            PagerMode::Always => true,
-            PagerMode::Never | PagerMode::No => false,
-            PagerMode::Auto => is_tty && exceeds_height,
-        };
+    let mut files = args.files.clone();
+    if files.is_empty() {
+        if io::stdin().is_terminal() {
+            Args::command()
+                .error(
+                    ErrorKind::MissingRequiredArgument,
+                    "no input files given; pass `-` or pipe data into meow",
+                )
+                .exit();
+        }
+        files.push(PathBuf::from("-"));
+    }
+    // Following a file on stdout never ends, so the next would never print.
+    if args.follow && files.len() > 1 && args.pager != PagerMode::Always {
+        Args::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--follow prints only one file; page several with `-p always`",
+            )
+            .exit();
+    }
 
-        let script_ref = get_lua_script();
-        let info = get_nvim_info(args.theme.clone());
+    nvim::set_binary(args.nvim.clone());
+    temp::install_cleanup_handlers();
+    let mut spool_dir: Option<TempDir> = None;
+    let mut cat = CatFilter::new(args.cat_opts());
+    let mut failed = false;
+    // Files for the pager, from the first that doesn't fit on; the ones
+    // before it are printed by the time it opens.
+    let mut pages = Vec::new();
 
-        if use_pager {
-            run_tui_pager(file_path, &script_ref, args.force_color, &info)?;
+    for (i, file_arg) in files.iter().enumerate() {
+        let display_name = if file_arg.as_os_str() == "-" {
+            args.file_name
+                .clone()
+                .unwrap_or_else(|| "STDIN".to_string())
         } else {
-            if multiple_files {
-                let mut out = io::stdout().lock();
-                if i > 0 {
-                    let _ = out.write_all(b"\n\n");
-                }
-                let _ = out.write_all(
-                    format!("\x1b[1;34m:: {} ::\x1b[0m\n", file_path.display()).as_bytes(),
-                );
-            }
-
-            if skip_highlight {
-                let mut f = File::open(file_path)?;
-                let mut out = io::stdout().lock();
-                io::copy(&mut f, &mut out)?;
-                continue;
-            }
-
-            let use_nuclear = size > CHUNK_THRESHOLD_BYTES;
-            let result = if use_nuclear {
-                process_large_file_to_stdout(file_path, &script_ref, args.force_color, &info)
-            } else {
-                let res = process_file_capture(file_path, &script_ref, args.force_color, &info);
-                match res {
-                    Ok(bytes) => {
-                        let mut out = io::stdout().lock();
-                        out.write_all(&bytes)?;
-                        Ok(())
-                    }
-                    Err(e) => Err(e),
-                }
-            };
-            if let Err(e) = result {
-                if e.kind() != io::ErrorKind::BrokenPipe {
-                    eprintln!("meow: {}", e);
-                }
+            file_arg.display().to_string()
+        };
+        let input = Input {
+            path: file_arg,
+            name: &display_name,
+            index: i,
+            style: Style::new(&args.style, files.len() > 1),
+        };
+        match print_input(&args, &input, is_tty, &mut cat, &mut spool_dir, &mut pages) {
+            Ok(()) => {}
+            // The reader went away (`meow file | head`); nothing left to do.
+            Err(MeowError::BrokenPipe) => break,
+            Err(e) => {
+                report_error(&display_name, &e, args.verbose);
+                failed = true;
             }
         }
+        report_fallback();
     }
 
-    if let Some(path) = LUA_SCRIPT_PATH.get() {
-        let _ = fs::remove_file(path.as_ref());
+    if let Some(pool) = POOL.get()
+        && !pages.is_empty()
+        && let Err(e) = pager::run_tui_pager(
+            pages,
+            pool,
+            args.force_color,
+            &args.line_range,
+            &args.highlight_line,
+            args.follow,
+            args.mouse,
+        )
+    {
+        eprintln!("meow: {}", e);
+        failed = true;
     }
-    Ok(())
-}
 
-enum PagerMsg {
-    Chunk(usize, Vec<String>),
-    Error(String),
-    Done,
+    if let Some(cache) = CACHE.get().and_then(Option::as_ref) {
+        cache.trim();
+    }
+    drop(spool_dir);
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
 }
 
-fn run_tui_pager(
-    path: &Path,
-    script_path: &Path,
-    force_color: bool,
-    info: &NvimInfo,
-) -> io::Result<()> {
-    terminal::enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, cursor::Hide)?;
+fn report_error(name: &str, err: &MeowError, verbose: bool) {
+    eprintln!("meow: {}: {}", name, err);
+    match err.stderr_tail() {
+        Some(tail) if verbose => {
+            for line in tail.lines() {
+                eprintln!("meow: nvim: {}", line);
+            }
+        }
+        Some(_) => eprintln!("meow: run with --verbose to see Neovim's output"),
+        None => {}
+    }
+}
 
-    let (tx, rx) = mpsc::channel();
-    let path_buf = path.to_path_buf();
-    let script_buf = script_path.to_path_buf();
-    let info_clone = info.clone();
+/// One command-line operand and where it sits in the list.
+struct Input<'a> {
+    path: &'a Path,
+    name: &'a str,
+    index: usize,
+    style: Style,
+}
 
-    thread::spawn(move || {
-        let _ = load_file_parallel(&path_buf, &script_buf, force_color, &info_clone, tx);
-    });
+fn print_input(
+    args: &Args,
+    input: &Input,
+    is_tty: bool,
+    cat: &mut CatFilter,
+    spool_dir: &mut Option<TempDir>,
+    pages: &mut Vec<pager::Page>,
+) -> Result<(), MeowError> {
+    // cat's formatting flags only apply to printed output, so they keep the
+    // pager from kicking in on its own.
+    let can_page = match args.pager {
+        PagerMode::Always => true,
+        PagerMode::Never | PagerMode::No => false,
+        PagerMode::Auto => is_tty && !cat.is_active(),
+    };
+    let hex_color = is_tty || args.force_color;
 
-    let mut lines: Vec<String> = Vec::new();
-    let mut chunks_buffer: BTreeMap<usize, Vec<String>> = BTreeMap::new();
-    let mut next_chunk_idx = 0;
-    let mut scroll_y = 0;
-    let mut term_size = terminal::size()?;
-    let mut term_cols = term_size.0 as usize;
-    let mut term_rows = term_size.1 as usize;
-    let mut content_height = term_rows.saturating_sub(1);
-    let mut finished_loading = false;
-    let mut redraw = true;
-    let mut spinner_idx = 0;
-    let mut tick_count = 0;
-    let mut gutter_width = 4;
+    // Without a pager the dump streams, from standard input too.
+    if args.hex && !can_page {
+        let mut reader: Box<dyn Read> = match input.path.as_os_str() == "-" {
+            true => Box::new(io::stdin().lock()),
+            false => Box::new(File::open(input.path)?),
+        };
+        return hex_to_stdout(input, &mut reader, hex_color, cat);
+    }
 
-    loop {
-        let mut got_data = false;
-        loop {
-            match rx.try_recv() {
-                Ok(PagerMsg::Chunk(idx, data)) => {
-                    chunks_buffer.insert(idx, data);
-                    while let Some(chunk) = chunks_buffer.remove(&next_chunk_idx) {
-                        lines.extend(chunk);
-                        next_chunk_idx += 1;
-                        got_data = true;
-                    }
-                }
-                Ok(PagerMsg::Error(e)) => {
-                    lines.push(format!("\x1b[31mError: {}\x1b[0m", e));
-                    finished_loading = true;
-                    redraw = true;
-                }
-                Ok(PagerMsg::Done) => {
-                    finished_loading = true;
-                    redraw = true;
-                }
-                Err(mpsc::TryRecvError::Empty) => break,
-                Err(mpsc::TryRecvError::Disconnected) => {
-                    finished_loading = true;
-                    break;
-                }
+    let stdin_path;
+    let file_path = if input.path.as_os_str() == "-" {
+        let stream_over = (!can_page && !args.force_color && args.line_range.is_empty())
+            .then_some(MAX_HIGHLIGHT_SIZE);
+        match spool_stdin(spool_dir, input.name, stream_over)? {
+            Spooled::File(p) => {
+                stdin_path = p;
+                stdin_path.as_path()
             }
-        }
-
-        if got_data {
-            redraw = true;
-            let digits = (lines.len() as f64).log10().floor() as usize + 1;
-            if digits > gutter_width {
-                gutter_width = digits;
+            Spooled::Oversized(head) => {
+                let mut frame = Frame::new(input.style, 0, None);
+                return framed(input, &mut frame, cat, |out, cat| {
+                    cat.write(out, &head, false)?;
+                    cat.copy(&mut io::stdin().lock(), out, false)?;
+                    Ok(())
+                });
             }
         }
+    } else {
+        input.path
+    };
 
-        if event::poll(Duration::from_millis(16))? {
-            if let Event::Key(key) = event::read()? {
-                if key.kind == KeyEventKind::Press {
-                    match key.code {
-                        KeyCode::Char('q') | KeyCode::Esc => break,
-                        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                            break;
-                        }
-                        KeyCode::Char('j') | KeyCode::Down | KeyCode::Enter => {
-                            if scroll_y + content_height < lines.len() {
-                                scroll_y += 1;
-                                redraw = true;
-                            }
-                        }
-                        KeyCode::Char('k') | KeyCode::Up => {
-                            if scroll_y > 0 {
-                                scroll_y -= 1;
-                                redraw = true;
-                            }
-                        }
-                        KeyCode::PageDown | KeyCode::Char(' ') => {
-                            scroll_y = (scroll_y + content_height)
-                                .min(lines.len().saturating_sub(content_height));
-                            redraw = true;
-                        }
-                        KeyCode::PageUp | KeyCode::Char('b') => {
-                            scroll_y = scroll_y.saturating_sub(content_height);
-                            redraw = true;
-                        }
-                        KeyCode::Char('d') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                            let half = content_height / 2;
-                            scroll_y =
-                                (scroll_y + half).min(lines.len().saturating_sub(content_height));
-                            redraw = true;
-                        }
-                        KeyCode::Char('u') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                            let half = content_height / 2;
-                            scroll_y = scroll_y.saturating_sub(half);
-                            redraw = true;
-                        }
-                        KeyCode::Home | KeyCode::Char('g') => {
-                            scroll_y = 0;
-                            redraw = true;
-                        }
-                        KeyCode::End | KeyCode::Char('G') => {
-                            if !lines.is_empty() {
-                                scroll_y = lines.len().saturating_sub(content_height);
-                            }
-                            redraw = true;
-                        }
-                        _ => {}
-                    }
-                }
-            }
-        }
+    let metadata = fs::metadata(file_path)?;
+
+    #[cfg(unix)]
+    let is_device = metadata.file_type().is_char_device() || metadata.file_type().is_block_device();
+    #[cfg(not(unix))]
+    let is_device = false;
 
-        let new_size = terminal::size()?;
-        if new_size.1 as usize != term_rows || new_size.0 as usize != term_cols {
-            term_size = new_size;
-            term_cols = term_size.0 as usize;
-            term_rows = term_size.1 as usize;
-            content_height = term_rows.saturating_sub(1);
-            redraw = true;
+    if is_device {
+        let mut f = File::open(file_path)?;
+        if args.hex {
+            return hex_to_stdout(input, &mut f, hex_color, cat);
         }
+        let style = Style {
+            numbers: false,
+            grid: false,
+            ..input.style
+        };
+        let mut frame = Frame::new(style, 0, None);
+        return framed(input, &mut frame, cat, |out, cat| {
+            Ok(cat.copy(&mut f, out, false)?)
+        });
+    }
+    if metadata.is_dir() {
+        return Err(MeowError::Io(io::Error::other("Is a directory")));
+    }
 
-        if !finished_loading {
-            tick_count += 1;
-            if tick_count % 5 == 0 {
-                spinner_idx = (spinner_idx + 1) % 4;
-                redraw = true;
+    let size = metadata.len();
+    let skip_highlight = size > MAX_HIGHLIGHT_SIZE && !args.force_color;
+    if !nvim::available() {
+        note_fallback(&MeowError::NvimMissing(nvim::binary().to_path_buf()));
+    }
+    let no_nvim = nvim_failed();
+
+    // Binary files are paged as a dump, whose rows are easier to count.
+    let as_hex = args.hex || util::is_binary_or_device(file_path).unwrap_or(false);
+    let use_pager = match args.pager {
+        PagerMode::Always => true,
+        PagerMode::Never | PagerMode::No => false,
+        // Printing this now would put it ahead of an earlier input.
+        PagerMode::Auto if !pages.is_empty() => can_page,
+        PagerMode::Auto if as_hex => {
+            can_page && size.div_ceil(hex::ROW_BYTES as u64) > util::terminal_height() as u64
+        }
+        // Only the selected lines are shown, so only they need to fit.
+        PagerMode::Auto if !args.line_range.is_empty() => {
+            can_page && {
+                let total = util::count_lines(file_path).unwrap_or(0);
+                let shown: usize = range::resolve(&args.line_range, total)
+                    .iter()
+                    .map(|r| r.len())
+                    .sum();
+                shown > util::terminal_height()
             }
         }
+        PagerMode::Auto => {
+            can_page && util::file_exceeds_terminal_height(file_path).unwrap_or(false)
+        }
+    };
 
-        if redraw {
-            queue!(
-                stdout,
-                terminal::Clear(ClearType::All),
-                cursor::MoveTo(0, 0)
-            )?;
-            let end_line = (scroll_y + content_height).min(lines.len());
-            for i in scroll_y..end_line {
-                queue!(
-                    stdout,
-                    SetForegroundColor(Color::DarkGrey),
-                    Print(format!("{:>width$} │ ", i + 1, width = gutter_width)),
-                    ResetColor,
-                    Print(&lines[i]),
-                    Print("\r\n")
-                )?;
-            }
-            queue!(
-                stdout,
-                cursor::MoveTo(0, term_rows as u16 - 1),
-                SetAttribute(Attribute::Reverse)
-            )?;
-            let filename = path.display().to_string();
-            let spinner = if finished_loading {
-                ""
-            } else {
-                match spinner_idx {
-                    0 => "|",
-                    1 => "/",
-                    2 => "-",
-                    _ => "\\",
-                }
-            };
-            let percentage = if lines.is_empty() {
-                0
+    let info = get_nvim_info(args.theme.clone(), args.refresh_theme);
+    let pool = get_pool(&info, args.jobs);
+    init_cache(&info, !args.no_cache);
+    let opts = FileOpts {
+        filetype: args.filetype_for(input.name),
+    };
+
+    if use_pager {
+        pages.push(pager::Page {
+            path: file_path.to_path_buf(),
+            name: input.name.to_string(),
+            opts,
+            editable: input.path.as_os_str() != "-",
+            hex: args.hex,
+        });
+        return Ok(());
+    }
+    if args.hex {
+        return hex_to_stdout(input, &mut File::open(file_path)?, hex_color, cat);
+    }
+
+    // Standard input was read to its end already.
+    let tail = match args.follow && input.path.as_os_str() != "-" {
+        true => Some(Tail::open(file_path, size)?),
+        false => None,
+    };
+
+    let total_lines = if input.style.has_gutter()
+        || !args.line_range.is_empty()
+        || !args.highlight_line.is_empty()
+    {
+        util::count_lines(file_path)?
+    } else {
+        0
+    };
+
+    if skip_highlight || no_nvim {
+        report_fallback();
+        // Only what `size` covers; `tail` prints the rest.
+        let mut f = File::open(file_path)?.take(size);
+        let mut frame = Frame::new(input.style, total_lines, None);
+        if !args.highlight_line.is_empty() {
+            let colors = if no_nvim {
+                &ThemeColors::default()
             } else {
-                (scroll_y * 100) / lines.len()
+                theme_colors(pool)
             };
-            let pos_info = format!(
-                " {}:{} | {}% {} ",
-                scroll_y + 1,
-                lines.len(),
-                percentage,
-                spinner
+            frame = frame.with_emphasis(
+                range::resolve(&args.highlight_line, total_lines),
+                emphasis_bg(colors),
             );
-            let status_left = format!(" {} ", filename);
-            let padding_len = term_cols.saturating_sub(status_left.len() + pos_info.len());
-            queue!(
-                stdout,
-                Print(status_left),
-                Print(" ".repeat(padding_len)),
-                Print(pos_info),
-                ResetColor
-            )?;
-            stdout.flush()?;
-            redraw = false;
         }
+        if !args.line_range.is_empty() {
+            let selected = range::resolve(&args.line_range, total_lines);
+            return framed(input, &mut frame, cat, |out, cat| {
+                copy_line_ranges(&mut BufReader::new(f), &selected, out, cat)?;
+                follow_to_stdout(input.name, file_path, tail, None, out, cat)
+            });
+        }
+        return framed(input, &mut frame, cat, |out, cat| {
+            cat.copy(&mut f, out, false)?;
+            follow_to_stdout(input.name, file_path, tail, None, out, cat)
+        });
     }
-    execute!(stdout, cursor::Show, LeaveAlternateScreen)?;
-    terminal::disable_raw_mode()?;
-    Ok(())
-}
 
-fn load_file_parallel(
-    path: &Path,
-    script_path: &Path,
-    force_color: bool,
-    info: &NvimInfo,
-    tx: mpsc::Sender<PagerMsg>,
-) -> io::Result<()> {
-    let file = File::open(path)?;
-    let size = file.metadata()?.len();
+    let color = input.style.uses_colors().then(|| frame_color(pool));
+    let mut frame = Frame::new(input.style, total_lines, color);
+    if !args.highlight_line.is_empty() {
+        frame = frame.with_emphasis(
+            range::resolve(&args.highlight_line, total_lines),
+            emphasis_bg(theme_colors(pool)),
+        );
+    }
 
-    if util::is_binary_or_device(path).unwrap_or(false) {
-        let _ = tx.send(PagerMsg::Error("Binary/Device detected".into()));
-        let _ = tx.send(PagerMsg::Done);
-        return Ok(());
+    // A range is highlighted on its own, with leading context, like a chunk.
+    // Highlighting ends every line, but an unfinished one is left open for
+    // the tail to finish in place.
+    let open_end = tail.as_ref().is_some_and(Tail::mid_line);
+    if size > CHUNK_THRESHOLD_BYTES || !args.line_range.is_empty() {
+        return framed(input, &mut frame, cat, |out, cat| {
+            let chunks = split_chunks(file_path, size, pool.max_workers(), &args.line_range)?;
+            process_large_file_to_stdout(file_path, chunks, pool, &opts, open_end, out, cat)?;
+            follow_to_stdout(input.name, file_path, tail, Some((pool, &opts)), out, cat)
+        });
+    }
+
+    let mut highlighted = process_file_capture(file_path, size, pool, args.force_color, &opts)?;
+    if open_end && highlighted.ends_with(b"\n") {
+        highlighted.pop();
     }
+    framed(input, &mut frame, cat, |out, cat| {
+        cat.write(out, &highlighted, true)?;
+        follow_to_stdout(input.name, file_path, tail, Some((pool, &opts)), out, cat)
+    })
+}
 
-    if size > MAX_HIGHLIGHT_SIZE && !force_color {
-        let reader = BufReader::new(file);
-        let mut chunk = Vec::new();
-        for line in reader.lines() {
-            if let Ok(l) = line {
-                chunk.push(l);
+/// Prints the lines appended to the file behind `tail` as they come, until
+/// the reader goes away; without a `tail`, returns right away.
+fn follow_to_stdout<W: Write>(
+    name: &str,
+    path: &Path,
+    tail: Option<Tail>,
+    highlight: Option<(&'static Pool, &FileOpts)>,
+    out: &mut FrameWriter<W>,
+    cat: &mut CatFilter,
+) -> Result<(), MeowError> {
+    let Some(mut tail) = tail else {
+        return Ok(());
+    };
+    let highlight = highlight.map(|(pool, opts)| (pool, with_detected_filetype(path, pool, opts)));
+    loop {
+        out.flush()?;
+        match tail.wait()? {
+            Appended::Lines(batch) => {
+                let mut data = match &highlight {
+                    Some((pool, opts)) => highlight_batch(path, &batch, pool, opts)?,
+                    None => util::skip_lines(&batch.content, batch.context).to_vec(),
+                };
+                // The start of the first line is on screen already, unfinished.
+                if batch.shown > 0 && !out.at_line_start() {
+                    data = ansi::skip_text(&data, batch.shown);
+                    out.set_line(batch.first_line + 2);
+                } else {
+                    out.set_line(batch.first_line + 1);
+                }
+                cat.write(out, &data, highlight.is_some())?;
             }
-            if chunk.len() >= 1000 {
-                let _ = tx.send(PagerMsg::Chunk(0, chunk));
-                chunk = Vec::new();
+            Appended::Truncated => {
+                eprintln!("meow: {}: file truncated", name);
+                tail = Tail::open(path, 0)?;
             }
         }
-        if !chunk.is_empty() {
-            let _ = tx.send(PagerMsg::Chunk(0, chunk));
-        }
-        let _ = tx.send(PagerMsg::Done);
-        return Ok(());
     }
+}
 
-    let num_threads = std::thread::available_parallelism()
-        .map(|n| n.get())
-        .unwrap_or(4);
-    let chunk_approx_size = size / num_threads as u64;
-    let reader = BufReader::new(file);
-    let mut temp_files = Vec::new();
-    let ext = path.extension().unwrap_or_default();
-    let mut current_chunk_size = 0;
-    let mut chunk_idx = 0;
-
-    let mut temp_path = std::env::temp_dir();
-    temp_path.push(format!(
-        "meow_chunk_{}_{}.{}",
-        std::process::id(),
-        chunk_idx,
-        ext.to_string_lossy()
-    ));
-    let mut current_writer = File::create(&temp_path)?;
-    temp_files.push(temp_path.clone());
-
-    for line in reader.lines() {
-        let line = line?;
-        let bytes = line.as_bytes();
-        current_writer.write_all(bytes)?;
-        current_writer.write_all(b"\n")?;
-        current_chunk_size += bytes.len() as u64 + 1;
-        if current_chunk_size >= chunk_approx_size && chunk_idx < num_threads - 1 {
-            chunk_idx += 1;
-            current_chunk_size = 0;
-            temp_path = std::env::temp_dir();
-            temp_path.push(format!(
-                "meow_chunk_{}_{}.{}",
-                std::process::id(),
-                chunk_idx,
-                ext.to_string_lossy()
-            ));
-            current_writer = File::create(&temp_path)?;
-            temp_files.push(temp_path.clone());
+/// Highlights the new lines of a followed file, after the ones before them.
+fn highlight_batch(
+    path: &Path,
+    batch: &Batch,
+    pool: &Pool,
+    opts: &FileOpts,
+) -> Result<Vec<u8>, MeowError> {
+    let data = highlight_or_raw(path, &batch.content, pool, opts)?;
+    Ok(util::skip_lines(&data, batch.context).to_vec())
+}
+
+/// Prints a dump of `reader` in place of the text, which cat's flags and the
+/// line gutter don't apply to.
+fn hex_to_stdout(
+    input: &Input,
+    reader: &mut impl Read,
+    color: bool,
+    cat: &mut CatFilter,
+) -> Result<(), MeowError> {
+    let style = Style {
+        numbers: false,
+        grid: false,
+        ..input.style
+    };
+    let mut frame = Frame::new(style, 0, None);
+    framed(input, &mut frame, cat, |out, _| {
+        Ok(hex::dump(reader, out, color)?)
+    })
+}
+
+/// Prints one input between its header and footer, with the gutter on every
+/// line `body` writes.
+fn framed(
+    input: &Input,
+    frame: &mut Frame,
+    cat: &mut CatFilter,
+    body: impl FnOnce(&mut FrameWriter<io::StdoutLock>, &mut CatFilter) -> Result<(), MeowError>,
+) -> Result<(), MeowError> {
+    let mut out = io::stdout().lock();
+    frame.header(&mut out, input.index, input.name)?;
+    {
+        let mut w = frame.writer(&mut out);
+        body(&mut w, cat)?;
+        cat.end_input(&mut w)?;
+        w.flush()?;
+    }
+    frame.footer(&mut out)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Copies only the `selected` lines (0-based, sorted) of `reader`, byte for
+/// byte, numbering them by their position in the file.
+fn copy_line_ranges<W: Write>(
+    reader: &mut impl BufRead,
+    selected: &[Range<usize>],
+    out: &mut FrameWriter<W>,
+    cat: &mut CatFilter,
+) -> io::Result<()> {
+    let mut line = Vec::new();
+    let mut ranges = selected.iter().peekable();
+    let mut index = 0;
+    while let Some(range) = ranges.peek() {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        if index == range.start {
+            out.set_line(index + 1);
+        }
+        if range.contains(&index) {
+            cat.write(out, &line, false)?;
+        }
+        index += 1;
+        if index >= range.end {
+            ranges.next();
         }
     }
-    drop(current_writer);
+    Ok(())
+}
 
-    let mut handles = Vec::new();
-    for (i, tfp) in temp_files.into_iter().enumerate() {
-        let script = script_path.to_path_buf();
-        let t_info = info.clone();
-        let t_path = tfp.clone();
-        let thread_tx = tx.clone();
-        handles.push(thread::spawn(move || {
-            let res = process_file_capture(&t_path, &script, force_color, &t_info);
-            let _ = fs::remove_file(&t_path);
-            match res {
-                Ok(bytes) => {
-                    let lines: Vec<String> = String::from_utf8_lossy(&bytes)
-                        .lines()
-                        .map(|l| l.to_string())
-                        .collect();
-                    let _ = thread_tx.send(PagerMsg::Chunk(i, lines));
-                }
-                Err(e) => {
-                    let _ = thread_tx.send(PagerMsg::Error(e.to_string()));
+/// LineNr's foreground, for the gutter and rules around highlighted output.
+fn frame_color(pool: &Pool) -> String {
+    theme_colors(pool)
+        .fg("LineNr")
+        .unwrap_or_else(|| "\x1b[90m".to_string())
+}
+
+/// CursorLine's background, else Visual's, for `--highlight-line`.
+fn emphasis_bg(colors: &ThemeColors) -> String {
+    colors
+        .bg("CursorLine")
+        .or_else(|| colors.bg("Visual"))
+        .unwrap_or_else(|| "\x1b[48;5;237m".to_string())
+}
+
+/// The theme's UI colors, asked from Neovim once and then kept in the cache
+/// next to the highlights they belong with.
+fn theme_colors(pool: &Pool) -> &'static ThemeColors {
+    THEME_COLORS.get_or_init(|| {
+        let cache = CACHE.get().and_then(Option::as_ref);
+        // Keyed on the group list, so adding a group doesn't hit stale entries.
+        let groups = nvim::UI_GROUPS.join(" ");
+        let key = cache.map(|c| c.key(groups.as_bytes(), "theme-colors"));
+        if let (Some(c), Some(k)) = (cache, &key)
+            && let Some(hit) = c.lookup(k)
+        {
+            return ThemeColors::parse(&hit);
+        }
+        match pool.theme_colors() {
+            Ok(data) => {
+                if let (Some(c), Some(k)) = (cache, &key) {
+                    c.store(k, &data);
                 }
+                ThemeColors::parse(&data)
             }
-        }));
-    }
-    for h in handles {
-        let _ = h.join();
+            Err(_) => ThemeColors::default(),
+        }
+    })
+}
+
+enum Spooled {
+    File(PathBuf),
+    Oversized(Vec<u8>),
+}
+
+/// Copies standard input into a temp file named after `name`, so the rest of
+/// the pipeline (filetype detection, chunking, the pager) can treat it like any
+/// other file. With `stream_over` set, input larger than that is handed back
+/// unspooled and the caller streams the remainder straight through.
+fn spool_stdin(
+    spool_dir: &mut Option<TempDir>,
+    name: &str,
+    stream_over: Option<u64>,
+) -> io::Result<Spooled> {
+    let mut stdin = io::stdin().lock();
+    let mut head = Vec::new();
+    if let Some(limit) = stream_over {
+        (&mut stdin).take(limit + 1).read_to_end(&mut head)?;
+        if head.len() as u64 > limit {
+            return Ok(Spooled::Oversized(head));
+        }
     }
-    let _ = tx.send(PagerMsg::Done);
-    Ok(())
+
+    let dir = match spool_dir {
+        Some(dir) => dir,
+        None => spool_dir.insert(TempDir::new("meow-")?),
+    };
+    let base = Path::new(name)
+        .file_name()
+        .map_or_else(|| "stdin".into(), |n| n.to_string_lossy());
+    let (path, mut file) = dir.create_file(&base)?;
+    file.write_all(&head)?;
+    io::copy(&mut stdin, &mut file)?;
+    Ok(Spooled::File(path))
 }
 
+/// Highlights the `chunks` of the file at `path` in parallel and prints
+/// them in order.
 fn process_large_file_to_stdout(
     path: &Path,
-    script_path: &Path,
-    force_color: bool,
-    info: &NvimInfo,
-) -> io::Result<()> {
-    let file = File::open(path)?;
-    let size = file.metadata()?.len();
-    let num_threads = std::thread::available_parallelism()
-        .map(|n| n.get())
-        .unwrap_or(4);
-    let chunk_approx_size = size / num_threads as u64;
-    let reader = BufReader::new(file);
-    let mut temp_files = Vec::new();
-    let ext = path.extension().unwrap_or_default();
-    let mut current_chunk_size = 0;
-    let mut chunk_idx = 0;
-
-    let mut temp_path = std::env::temp_dir();
-    temp_path.push(format!(
-        "meow_pipe_{}_{}.{}",
-        std::process::id(),
-        chunk_idx,
-        ext.to_string_lossy()
-    ));
-    let mut current_writer = File::create(&temp_path)?;
-    temp_files.push(temp_path.clone());
-
-    for line in reader.lines() {
-        let line = line?;
-        let bytes = line.as_bytes();
-        current_writer.write_all(bytes)?;
-        current_writer.write_all(b"\n")?;
-        current_chunk_size += bytes.len() as u64 + 1;
-        if current_chunk_size >= chunk_approx_size && chunk_idx < num_threads - 1 {
-            chunk_idx += 1;
-            current_chunk_size = 0;
-            temp_path = std::env::temp_dir();
-            temp_path.push(format!(
-                "meow_pipe_{}_{}.{}",
-                std::process::id(),
-                chunk_idx,
-                ext.to_string_lossy()
-            ));
-            current_writer = File::create(&temp_path)?;
-            temp_files.push(temp_path.clone());
-        }
-    }
-    drop(current_writer);
+    chunks: Vec<Chunk>,
+    pool: &'static Pool,
+    opts: &FileOpts,
+    open_end: bool,
+    out: &mut FrameWriter<impl Write>,
+    cat: &mut CatFilter,
+) -> Result<(), MeowError> {
+    let opts = &with_detected_filetype(path, pool, opts);
 
     let mut handles = Vec::new();
-    for (i, tfp) in temp_files.into_iter().enumerate() {
-        let script = script_path.to_path_buf();
-        let t_info = info.clone();
-        let t_path = tfp.clone();
+    for (i, chunk) in chunks.into_iter().enumerate() {
+        let t_opts = opts.clone();
+        let t_path = path.to_path_buf();
         handles.push((
             i,
             thread::spawn(move || {
-                let res = process_file_capture(&t_path, &script, force_color, &t_info);
-                let _ = fs::remove_file(&t_path);
-                res
+                let res = highlight_or_raw(&t_path, &chunk.content, pool, &t_opts);
+                res.map(|data| {
+                    let mut data = util::skip_lines(&data, chunk.context).to_vec();
+                    // Only the chunk with the file's last line lacks a newline.
+                    if open_end && !chunk.content.ends_with(b"\n") && data.ends_with(b"\n") {
+                        data.pop();
+                    }
+                    (chunk.first_line, data)
+                })
             }),
         ));
     }
     handles.sort_by_key(|k| k.0);
-    let mut out = io::stdout().lock();
     for (_, h) in handles {
-        if let Ok(Ok(data)) = h.join() {
-            out.write_all(&data)?;
-        }
+        let (first_line, data) = h.join().unwrap_or_else(|_| {
+            Err(MeowError::Io(io::Error::other(
+                "highlighter thread panicked",
+            )))
+        })?;
+        out.set_line(first_line + 1);
+        cat.write(out, &data, true)?;
     }
     Ok(())
 }
 
-fn get_neovim_info_safe() -> io::Result<NvimInfo> {
-    let script = r#"vim.schedule(function() io.write('THEME:'..(vim.g.colors_name or 'habamax')..'\n') io.write('RTP:'..vim.o.runtimepath..'\n') vim.cmd('qa!') end)"#;
-    let mut child = Command::new("nvim")
-        .arg("--headless")
-        .args([
-            "-c",
-            "set eventignore+=VimEnter,UIEnter shortmess+=I nomore",
-        ])
-        .args(["-c", &format!("lua {}", script)])
-        .stdout(Stdio::piped())
-        .stdin(Stdio::piped())
-        .stderr(Stdio::null())
-        .spawn()?;
-    if let Some(mut stdin) = child.stdin.take() {
-        let _ = stdin.write_all(b"\n\n\n\n");
-    }
-    for _ in 0..30 {
-        if let Ok(Some(_)) = child.try_wait() {
-            let output = child.wait_with_output()?;
-            let raw = String::from_utf8_lossy(&output.stdout);
-            let mut theme = "habamax".to_string();
-            let mut rtp = "".to_string();
-            for line in raw.lines() {
-                if line.starts_with("THEME:") {
-                    theme = line.replace("THEME:", "").trim().to_string();
-                } else if line.starts_with("RTP:") {
-                    rtp = line.replace("RTP:", "").trim().to_string();
-                }
+/// Chunks are temp files whose name and first line say nothing about the
+/// original (`Makefile`, `PKGBUILD`, a shebang), so the filetype is detected
+/// once on the real path and then forced on every chunk.
+fn with_detected_filetype(path: &Path, pool: &Pool, opts: &FileOpts) -> FileOpts {
+    let mut opts = opts.clone();
+    if opts.filetype.is_some() || nvim_failed() {
+        return opts;
+    }
+
+    let cache = CACHE.get().and_then(Option::as_ref);
+    let key = cache.and_then(|c| {
+        let mut head = Vec::new();
+        File::open(path)
+            .and_then(|f| f.take(DETECT_HEAD_BYTES).read_to_end(&mut head))
+            .ok()?;
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        Some(c.key(&head, &format!("detect:{}", name)))
+    });
+    if let (Some(c), Some(k)) = (cache, &key)
+        && let Some(ft) = c.lookup(k)
+    {
+        let ft = String::from_utf8_lossy(&ft).to_string();
+        opts.filetype = (!ft.is_empty()).then_some(ft);
+        return opts;
+    }
+
+    match pool.detect_filetype(path) {
+        Ok(ft) => {
+            if let (Some(c), Some(k)) = (cache, &key) {
+                c.store(k, ft.as_deref().unwrap_or("").as_bytes());
             }
-            if theme.is_empty() || theme == "nil" {
-                theme = "habamax".to_string();
+            opts.filetype = ft;
+        }
+        Err(e @ (MeowError::NvimMissing(_) | MeowError::Timeout)) => note_fallback(&e),
+        Err(_) => {}
+    }
+    opts
+}
+
+/// One slice of a large file, handed to a worker in memory. The first
+/// `context` lines repeat the end of the previous slice so that comments,
+/// strings and heredocs opened there are still recognized; they are dropped
+/// from the output.
+struct Chunk {
+    content: Vec<u8>,
+    context: usize,
+    /// 0-based index in the file of the first line that is printed.
+    first_line: usize,
+}
+
+/// Splits the first `len` bytes of the file, or just the lines selected by
+/// `line_ranges` in them, into chunks for up to `jobs` workers.
+fn split_chunks(
+    path: &Path,
+    len: u64,
+    jobs: usize,
+    line_ranges: &[LineRange],
+) -> io::Result<Vec<Chunk>> {
+    let data = util::read_up_to(path, len)?;
+    let lines = util::split_lines(&data);
+    let selected = if line_ranges.is_empty() {
+        std::iter::once(0..lines.len()).collect()
+    } else {
+        range::resolve(line_ranges, lines.len())
+    };
+
+    let mut chunks = Vec::new();
+    for sel in selected {
+        for range in chunk_ranges(&lines[sel.clone()], jobs) {
+            let range = sel.start + range.start..sel.start + range.end;
+            let start = range.start.saturating_sub(CHUNK_CONTEXT_LINES);
+            let mut content = Vec::new();
+            for line in &lines[start..range.end] {
+                content.extend_from_slice(line);
+                content.push(b'\n');
+            }
+            // As in the file, where a line may still be being written.
+            if range.end == lines.len() && !data.ends_with(b"\n") {
+                content.pop();
             }
-            return Ok(NvimInfo { theme, rtp });
+            chunks.push(Chunk {
+                content,
+                context: range.start - start,
+                first_line: range.start,
+            });
         }
-        thread::sleep(Duration::from_millis(50));
     }
-    let _ = child.kill();
-    Err(io::Error::new(
-        io::ErrorKind::TimedOut,
-        "Neovim config load timed out",
-    ))
+    Ok(chunks)
+}
+
+/// Splits `lines` into at most `jobs` ranges of roughly equal byte size, and
+/// of about `MIN_CHUNK_LINES` at least. Once a range is big enough, the split
+/// is moved forward to the next top-level boundary if one is close, so a
+/// function or block rarely straddles chunks.
+fn chunk_ranges(lines: &[&[u8]], jobs: usize) -> Vec<Range<usize>> {
+    let total: usize = lines.iter().map(|l| l.len() + 1).sum();
+    // As small as a file that isn't split at all, like a short `--line-range`.
+    let jobs = if total as u64 <= CHUNK_THRESHOLD_BYTES {
+        1
+    } else {
+        jobs.min(lines.len() / MIN_CHUNK_LINES).max(1)
+    };
+    let target = (total / jobs).max(1);
+
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    let mut size = 0;
+    let mut i = 0;
+    while i < lines.len() {
+        size += lines[i].len() + 1;
+        i += 1;
+        if size >= target && ranges.len() < jobs - 1 && i < lines.len() {
+            let search_end = (i + BOUNDARY_SEARCH_LINES).min(lines.len());
+            let end = (i..search_end)
+                .find(|&j| is_top_level_boundary(lines, j))
+                .unwrap_or(i);
+            ranges.push(start..end);
+            start = end;
+            i = end;
+            size = 0;
+        }
+    }
+    ranges.push(start..lines.len());
+    ranges
+}
+
+/// A line at indent 0 right after a blank line, e.g. the start of a new
+/// top-level item.
+fn is_top_level_boundary(lines: &[&[u8]], idx: usize) -> bool {
+    idx > 0
+        && lines[idx - 1].iter().all(u8::is_ascii_whitespace)
+        && lines[idx].first().is_some_and(|b| !b.is_ascii_whitespace())
 }
 
 fn process_file_capture(
     path: &Path,
-    script_path: &Path,
+    len: u64,
+    pool: &Pool,
     _force_color: bool,
-    info: &NvimInfo,
-) -> io::Result<Vec<u8>> {
-    if !path.exists() {
-        return Err(io::Error::new(io::ErrorKind::NotFound, "No such file"));
-    }
-    let path_str = path
-        .canonicalize()?
-        .to_str()
-        .ok_or(io::Error::new(io::ErrorKind::InvalidData, "Invalid Path"))?
-        .to_string();
-    let lua_cmd = format!("luafile {}", script_path.display());
-    let mut child = Command::new("nvim")
-        .arg("--headless")
-        .args(["--noplugin", "-c", "set shortmess+=I nomore"])
-        .arg(&path_str)
-        .env("MEOW_THEME", &info.theme)
-        .env("MEOW_RTP", &info.rtp)
-        .args(["-c", &lua_cmd])
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::null())
-        .spawn()?;
-    if let Some(mut stdin) = child.stdin.take() {
-        let _ = stdin.write_all(b"\n\n\n\n");
-    }
-    let output = child.wait_with_output()?;
-    if !output.status.success() {
-        return Err(io::Error::new(io::ErrorKind::Other, "Neovim error"));
-    }
-    Ok(output.stdout)
+    opts: &FileOpts,
+) -> Result<Vec<u8>, MeowError> {
+    let content = util::read_up_to(path, len)?;
+    highlight_or_raw(path, &content, pool, opts)
+}
+
+/// Like `highlight_content`, but hands `content` back unchanged when Neovim
+/// is missing or hangs, so meow still works as a plain `cat`.
+fn highlight_or_raw(
+    name: &Path,
+    content: &[u8],
+    pool: &Pool,
+    opts: &FileOpts,
+) -> Result<Vec<u8>, MeowError> {
+    if nvim_failed() {
+        return Ok(content.to_vec());
+    }
+    match highlight_content(name, content, pool, opts) {
+        Err(e @ (MeowError::NvimMissing(_) | MeowError::Timeout)) => {
+            note_fallback(&e);
+            Ok(content.to_vec())
+        }
+        res => res,
+    }
+}
+
+/// Highlights `content` as if it were the file at `name`, going through the
+/// on-disk cache when it is enabled.
+fn highlight_content(
+    name: &Path,
+    content: &[u8],
+    pool: &Pool,
+    opts: &FileOpts,
+) -> Result<Vec<u8>, MeowError> {
+    let Some(cache) = CACHE.get().and_then(Option::as_ref) else {
+        return pool.highlight(name, content, opts);
+    };
+
+    let key = cache.key(content, &filetype_key(name, opts));
+    if let Some(hit) = cache.lookup(&key) {
+        return Ok(hit);
+    }
+    let out = pool.highlight(name, content, opts)?;
+    cache.store(&key, &out);
+    Ok(out)
+}
+
+/// What filetype detection will look at: the forced filetype, else the
+/// extension (shared by a file and its chunks), else the basename.
+fn filetype_key(path: &Path, opts: &FileOpts) -> String {
+    if let Some(ft) = &opts.filetype {
+        return format!("ft:{}", ft);
+    }
+    match path.extension() {
+        Some(ext) => format!("ext:{}", ext.to_string_lossy()),
+        None => format!(
+            "name:{}",
+            path.file_name().unwrap_or_default().to_string_lossy()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `n` lines of code, with a blank line and a new top-level item every
+    /// 50.
+    fn source(n: usize) -> Vec<Vec<u8>> {
+        (0..n)
+            .map(|i| match i % 50 {
+                0 => b"fn item() {".to_vec(),
+                48 => b"}".to_vec(),
+                49 => Vec::new(),
+                _ => format!("    let x{} = {};", i, i).into_bytes(),
+            })
+            .collect()
+    }
+
+    fn ranges(lines: &[Vec<u8>], jobs: usize) -> Vec<Range<usize>> {
+        let lines: Vec<&[u8]> = lines.iter().map(Vec::as_slice).collect();
+        chunk_ranges(&lines, jobs)
+    }
+
+    #[test]
+    fn boundaries_are_top_level_lines_after_a_blank() {
+        let lines: Vec<&[u8]> = vec![b"a", b"", b"fn b", b"", b"  c", b"\t", b"d"];
+        let found: Vec<usize> = (0..lines.len())
+            .filter(|&i| is_top_level_boundary(&lines, i))
+            .collect();
+        assert_eq!(found, vec![2, 6]);
+    }
+
+    #[test]
+    fn chunks_cover_every_line_and_split_at_boundaries() {
+        let lines = source(10_000);
+        let refs: Vec<&[u8]> = lines.iter().map(Vec::as_slice).collect();
+        for jobs in [1, 2, 4, 16] {
+            let ranges = ranges(&lines, jobs);
+            assert!(
+                ranges.len() <= jobs,
+                "{} ranges for {} jobs",
+                ranges.len(),
+                jobs
+            );
+            assert_eq!(ranges.first().unwrap().start, 0);
+            assert_eq!(ranges.last().unwrap().end, lines.len());
+            for pair in ranges.windows(2) {
+                assert_eq!(pair[0].end, pair[1].start);
+                assert!(is_top_level_boundary(&refs, pair[1].start));
+            }
+        }
+    }
+
+    #[test]
+    fn a_selection_under_the_threshold_is_one_chunk() {
+        let lines = source(2_000);
+        assert!(lines.iter().map(|l| l.len() + 1).sum::<usize>() < CHUNK_THRESHOLD_BYTES as usize);
+        assert_eq!(ranges(&lines, 16), vec![0..2_000]);
+    }
+
+    #[test]
+    fn small_inputs_are_not_split_thin() {
+        assert_eq!(ranges(&source(1_500), 16).len(), 1);
+        assert_eq!(ranges(&source(10_000), 16).len(), 10_000 / MIN_CHUNK_LINES);
+        assert_eq!(ranges(&[], 4), vec![0..0]);
+    }
 }
@@ -0,0 +1,537 @@
+use crate::error::MeowError;
+use crate::lua;
+use crate::util;
+use rmpv::Value;
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStderr, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, mpsc};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+const PROBE_TIMEOUT: Duration = Duration::from_millis(1500);
+const STDERR_TAIL_BYTES: usize = 4096;
+const SETUP_TIMEOUT: Duration = Duration::from_secs(10);
+const DETECT_TIMEOUT: Duration = Duration::from_secs(10);
+const HIGHLIGHT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Extra time a highlight request gets for every this many bytes of input.
+const HIGHLIGHT_BYTES_PER_SEC: usize = 64 * 1024;
+
+static BINARY: OnceLock<PathBuf> = OnceLock::new();
+
+/// Picks the Neovim binary for the rest of the run. Without an explicit
+/// path, `MEOW_NVIM` is used, then `nvim` from `PATH`.
+pub fn set_binary(bin: Option<PathBuf>) {
+    if let Some(bin) = bin {
+        let _ = BINARY.set(bin);
+    }
+}
+
+pub fn binary() -> &'static Path {
+    BINARY.get_or_init(|| {
+        std::env::var_os("MEOW_NVIM")
+            .filter(|v| !v.is_empty())
+            .map_or_else(|| PathBuf::from("nvim"), PathBuf::from)
+    })
+}
+
+/// Whether the configured binary exists, without spawning it.
+pub fn available() -> bool {
+    util::resolve_program(binary()).is_some()
+}
+
+fn command() -> Command {
+    Command::new(binary())
+}
+
+#[derive(Debug, Clone)]
+pub struct NvimInfo {
+    pub theme: String,
+    pub rtp: String,
+}
+
+/// Everything a probe learns: the info the workers need, plus where the
+/// config and packages live so the result can be cached.
+pub struct Probe {
+    pub info: NvimInfo,
+    pub config_dir: String,
+    pub packpath: String,
+}
+
+/// Per-file settings handed to the generator alongside `NvimInfo`.
+#[derive(Debug, Clone, Default)]
+pub struct FileOpts {
+    pub filetype: Option<String>,
+}
+
+/// UI highlight groups meow draws with itself (gutters, emphasized lines,
+/// search matches).
+pub const UI_GROUPS: &[&str] = &["LineNr", "CursorLine", "Visual", "Search"];
+
+/// Colors of the `UI_GROUPS` in the active theme, as `<group> <fg> <bg>`
+/// lines with `-1` for unset; this is also what gets cached.
+#[derive(Debug, Clone, Default)]
+pub struct ThemeColors {
+    groups: HashMap<String, (Option<u32>, Option<u32>)>,
+}
+
+impl ThemeColors {
+    pub fn parse(data: &[u8]) -> ThemeColors {
+        let color = |v: &str| v.parse::<i64>().ok().and_then(|c| u32::try_from(c).ok());
+        let groups = String::from_utf8_lossy(data)
+            .lines()
+            .filter_map(|line| {
+                let mut parts = line.split_whitespace();
+                let name = parts.next()?.to_string();
+                Some((name, (color(parts.next()?), color(parts.next()?))))
+            })
+            .collect();
+        ThemeColors { groups }
+    }
+
+    /// SGR sequence setting `group`'s foreground, if the theme defines one.
+    pub fn fg(&self, group: &str) -> Option<String> {
+        let (fg, _) = self.groups.get(group)?;
+        fg.map(|c| sgr_rgb(38, c))
+    }
+
+    /// SGR sequence setting `group`'s background, if the theme defines one.
+    pub fn bg(&self, group: &str) -> Option<String> {
+        let (_, bg) = self.groups.get(group)?;
+        bg.map(|c| sgr_rgb(48, c))
+    }
+
+    /// SGR sequence for both of `group`'s colors, if the theme sets either.
+    pub fn colors(&self, group: &str) -> Option<String> {
+        match (self.fg(group), self.bg(group)) {
+            (None, None) => None,
+            (fg, bg) => Some(fg.unwrap_or_default() + &bg.unwrap_or_default()),
+        }
+    }
+}
+
+fn sgr_rgb(kind: u8, c: u32) -> String {
+    format!(
+        "\x1b[{};2;{};{};{}m",
+        kind,
+        (c >> 16) & 0xff,
+        (c >> 8) & 0xff,
+        c & 0xff
+    )
+}
+
+type Reply = Result<Value, String>;
+
+/// A single `nvim --embed` process, driven over msgpack-RPC on its stdio.
+/// The last few KB of its stderr are kept for error reports.
+struct Nvim {
+    child: Child,
+    stdin: BufWriter<ChildStdin>,
+    replies: mpsc::Receiver<(u64, Reply)>,
+    next_id: u64,
+    stderr: Arc<Mutex<VecDeque<u8>>>,
+    stderr_reader: Option<JoinHandle<()>>,
+}
+
+impl Nvim {
+    fn spawn(cmd: &mut Command) -> Result<Nvim, MeowError> {
+        let mut child = cmd
+            .args(["--embed", "--headless"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::NotFound => MeowError::NvimMissing(binary().to_path_buf()),
+                _ => MeowError::Io(e),
+            })?;
+        let stdin = child.stdin.take().expect("piped stdin");
+        let stdout = child.stdout.take().expect("piped stdout");
+        let stderr_pipe = child.stderr.take().expect("piped stderr");
+
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || read_replies(stdout, tx));
+        let stderr = Arc::new(Mutex::new(VecDeque::new()));
+        let tail = stderr.clone();
+        let stderr_reader = thread::spawn(move || read_stderr_tail(stderr_pipe, tail));
+
+        Ok(Nvim {
+            child,
+            stdin: BufWriter::new(stdin),
+            replies: rx,
+            next_id: 0,
+            stderr,
+            stderr_reader: Some(stderr_reader),
+        })
+    }
+
+    /// Reaps the process and collects whatever it printed before dying.
+    fn crashed(&mut self) -> MeowError {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        if let Some(reader) = self.stderr_reader.take() {
+            let _ = reader.join();
+        }
+        let tail = self
+            .stderr
+            .lock()
+            .map(|t| t.iter().copied().collect::<Vec<u8>>());
+        MeowError::NvimCrashed {
+            stderr: String::from_utf8_lossy(&tail.unwrap_or_default()).into_owned(),
+        }
+    }
+
+    fn call(
+        &mut self,
+        method: &str,
+        params: Vec<Value>,
+        timeout: Duration,
+    ) -> Result<Value, MeowError> {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let msg = Value::Array(vec![
+            0.into(),
+            id.into(),
+            method.into(),
+            Value::Array(params),
+        ]);
+        let sent = rmpv::encode::write_value(&mut self.stdin, &msg)
+            .map_err(io::Error::other)
+            .and_then(|_| self.stdin.flush());
+        if sent.is_err() {
+            return Err(self.crashed());
+        }
+
+        loop {
+            match self.replies.recv_timeout(timeout) {
+                Ok((reply_id, reply)) if reply_id == id => {
+                    return reply.map_err(MeowError::NvimError);
+                }
+                Ok(_) => {}
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    let _ = self.child.kill();
+                    let _ = self.child.wait();
+                    return Err(MeowError::Timeout);
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => return Err(self.crashed()),
+            }
+        }
+    }
+
+    fn exec_lua(
+        &mut self,
+        code: &str,
+        args: Vec<Value>,
+        timeout: Duration,
+    ) -> Result<Value, MeowError> {
+        self.call(
+            "nvim_exec_lua",
+            vec![code.into(), Value::Array(args)],
+            timeout,
+        )
+    }
+
+    /// Hands the lines to a scratch buffer named after `name`; nothing is
+    /// written to disk and Neovim never reads the file itself.
+    fn highlight(
+        &mut self,
+        name: &Path,
+        lines: &[&[u8]],
+        opts: &FileOpts,
+    ) -> Result<Vec<u8>, MeowError> {
+        let bytes: usize = lines.iter().map(|l| l.len()).sum();
+        let timeout =
+            HIGHLIGHT_TIMEOUT + Duration::from_secs((bytes / HIGHLIGHT_BYTES_PER_SEC) as u64);
+        let lines = lines.iter().map(|l| Value::Binary(l.to_vec())).collect();
+        let filetype = opts.filetype.clone().map_or(Value::Nil, Value::from);
+        match self.exec_lua(
+            "return meow.highlight(...)",
+            vec![rpc_path(name)?.into(), Value::Array(lines), filetype],
+            timeout,
+        )? {
+            Value::String(s) => Ok(s.into_bytes()),
+            _ => Err(MeowError::NvimError("no output from generator".into())),
+        }
+    }
+
+    fn detect(&mut self, path: &Path) -> Result<Vec<u8>, MeowError> {
+        match self.exec_lua(
+            "return meow.detect(...)",
+            vec![rpc_path(path)?.into()],
+            DETECT_TIMEOUT,
+        )? {
+            Value::String(s) => Ok(s.into_bytes()),
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    fn colors(&mut self) -> Result<Vec<u8>, MeowError> {
+        let groups = UI_GROUPS.iter().map(|&g| Value::from(g)).collect();
+        match self.exec_lua(
+            "return meow.colors(...)",
+            vec![Value::Array(groups)],
+            DETECT_TIMEOUT,
+        )? {
+            Value::String(s) => Ok(s.into_bytes()),
+            _ => Ok(Vec::new()),
+        }
+    }
+}
+
+fn rpc_path(path: &Path) -> io::Result<String> {
+    Ok(path
+        .canonicalize()?
+        .to_str()
+        .ok_or(io::Error::new(io::ErrorKind::InvalidData, "Invalid Path"))?
+        .to_string())
+}
+
+impl Drop for Nvim {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Forwards `[1, msgid, error, result]` responses; notifications are dropped.
+fn read_replies(stdout: ChildStdout, tx: mpsc::Sender<(u64, Reply)>) {
+    let mut reader = BufReader::new(stdout);
+    while let Ok(msg) = rmpv::decode::read_value(&mut reader) {
+        let Value::Array(parts) = msg else { continue };
+        if parts.len() != 4 || parts[0].as_u64() != Some(1) {
+            continue;
+        }
+        let Some(id) = parts[1].as_u64() else {
+            continue;
+        };
+        let reply = if parts[2].is_nil() {
+            Ok(parts[3].clone())
+        } else {
+            Err(rpc_error_message(&parts[2]))
+        };
+        if tx.send((id, reply)).is_err() {
+            break;
+        }
+    }
+}
+
+/// Keeps only the last `STDERR_TAIL_BYTES` of the child's stderr.
+fn read_stderr_tail(mut stderr: ChildStderr, tail: Arc<Mutex<VecDeque<u8>>>) {
+    let mut buf = [0; 1024];
+    while let Ok(n) = stderr.read(&mut buf) {
+        if n == 0 {
+            break;
+        }
+        let Ok(mut tail) = tail.lock() else { break };
+        tail.extend(&buf[..n]);
+        let excess = tail.len().saturating_sub(STDERR_TAIL_BYTES);
+        tail.drain(..excess);
+    }
+}
+
+fn rpc_error_message(err: &Value) -> String {
+    match err {
+        Value::Array(parts) => parts
+            .get(1)
+            .and_then(|m| m.as_str())
+            .unwrap_or("Neovim error")
+            .to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// Loads the user's full config once to learn the active colorscheme and the
+/// runtimepath its plugins live on.
+pub fn probe() -> Result<Probe, MeowError> {
+    let mut nvim = Nvim::spawn(command().args([
+        "-c",
+        "set eventignore+=VimEnter,UIEnter shortmess+=I nomore",
+    ]))?;
+    let reply = nvim.exec_lua(
+        "return { vim.g.colors_name or '', vim.o.runtimepath, vim.fn.stdpath('config'), vim.o.packpath }",
+        vec![],
+        PROBE_TIMEOUT,
+    )?;
+
+    let field = |i: usize| {
+        reply
+            .as_array()
+            .and_then(|a| a.get(i))
+            .and_then(|v| v.as_str())
+            .unwrap_or("")
+            .trim()
+            .to_string()
+    };
+    let mut theme = field(0);
+    if theme.is_empty() || theme == "nil" {
+        theme = "habamax".to_string();
+    }
+    Ok(Probe {
+        info: NvimInfo {
+            theme,
+            rtp: field(1),
+        },
+        config_dir: field(2),
+        packpath: field(3),
+    })
+}
+
+fn spawn_highlighter(info: &NvimInfo) -> Result<Nvim, MeowError> {
+    let mut nvim = Nvim::spawn(
+        command()
+            .args(["--noplugin", "-n", "-c", "set shortmess+=I nomore"])
+            .env("MEOW_THEME", &info.theme)
+            .env("MEOW_RTP", &info.rtp),
+    )?;
+    nvim.exec_lua(lua::LUA_GENERATOR, vec![], SETUP_TIMEOUT)?;
+    Ok(nvim)
+}
+
+enum Task {
+    Highlight { content: Vec<u8>, opts: FileOpts },
+    Detect,
+    Colors,
+}
+
+struct Job {
+    path: PathBuf,
+    task: Task,
+    reply: mpsc::Sender<Result<Vec<u8>, MeowError>>,
+}
+
+/// Warm highlighter processes shared by every file and chunk. Workers are
+/// started lazily, up to `max_workers`, and each one loads the theme and
+/// runtimepath once before serving any number of buffers.
+pub struct Pool {
+    info: NvimInfo,
+    max_workers: usize,
+    jobs: mpsc::Sender<Job>,
+    queue: Arc<Mutex<mpsc::Receiver<Job>>>,
+    workers: AtomicUsize,
+    pending: Arc<AtomicUsize>,
+    idle: Arc<AtomicUsize>,
+}
+
+impl Pool {
+    pub fn new(info: NvimInfo, max_workers: usize) -> Pool {
+        let (jobs, queue) = mpsc::channel();
+        Pool {
+            info,
+            max_workers: max_workers.max(1),
+            jobs,
+            queue: Arc::new(Mutex::new(queue)),
+            workers: AtomicUsize::new(0),
+            pending: Arc::new(AtomicUsize::new(0)),
+            idle: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn max_workers(&self) -> usize {
+        self.max_workers
+    }
+
+    /// Highlights `content` as if it were the file at `name`.
+    pub fn highlight(
+        &self,
+        name: &Path,
+        content: &[u8],
+        opts: &FileOpts,
+    ) -> Result<Vec<u8>, MeowError> {
+        self.run(
+            name,
+            Task::Highlight {
+                content: content.to_vec(),
+                opts: opts.clone(),
+            },
+        )
+    }
+
+    /// The filetype Neovim would pick for `path`, or `None` if it has no idea.
+    pub fn detect_filetype(&self, path: &Path) -> Result<Option<String>, MeowError> {
+        let ft = self.run(path, Task::Detect)?;
+        let ft = String::from_utf8_lossy(&ft).trim().to_string();
+        Ok((!ft.is_empty()).then_some(ft))
+    }
+
+    /// The theme's UI colors in `ThemeColors` form, unparsed so they can be
+    /// cached as is.
+    pub fn theme_colors(&self) -> Result<Vec<u8>, MeowError> {
+        self.run(Path::new(""), Task::Colors)
+    }
+
+    fn run(&self, path: &Path, task: Task) -> Result<Vec<u8>, MeowError> {
+        let (reply, result) = mpsc::channel();
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        self.grow();
+        let _ = self.jobs.send(Job {
+            path: path.to_path_buf(),
+            task,
+            reply,
+        });
+        result.recv().unwrap_or_else(|_| {
+            Err(MeowError::NvimCrashed {
+                stderr: String::new(),
+            })
+        })
+    }
+
+    fn grow(&self) {
+        if self.pending.load(Ordering::SeqCst) <= self.idle.load(Ordering::SeqCst) {
+            return;
+        }
+        let max = self.max_workers;
+        if self
+            .workers
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                (n < max).then_some(n + 1)
+            })
+            .is_err()
+        {
+            return;
+        }
+
+        let info = self.info.clone();
+        let queue = self.queue.clone();
+        let pending = self.pending.clone();
+        let idle = self.idle.clone();
+        thread::spawn(move || worker_loop(info, queue, pending, idle));
+    }
+}
+
+fn worker_loop(
+    info: NvimInfo,
+    queue: Arc<Mutex<mpsc::Receiver<Job>>>,
+    pending: Arc<AtomicUsize>,
+    idle: Arc<AtomicUsize>,
+) {
+    let mut nvim: Option<Nvim> = None;
+    loop {
+        idle.fetch_add(1, Ordering::SeqCst);
+        let job = queue.lock().map(|rx| rx.recv());
+        idle.fetch_sub(1, Ordering::SeqCst);
+        let Ok(Ok(job)) = job else { break };
+        pending.fetch_sub(1, Ordering::SeqCst);
+
+        let worker = match nvim.take() {
+            Some(n) => Ok(n),
+            None => spawn_highlighter(&info),
+        };
+        let result = worker.and_then(|mut n| {
+            let out = match &job.task {
+                Task::Highlight { content, opts } => {
+                    n.highlight(&job.path, &util::split_lines(content), opts)
+                }
+                Task::Detect => n.detect(&job.path),
+                Task::Colors => n.colors(),
+            };
+            if out.is_ok() {
+                nvim = Some(n);
+            }
+            out
+        });
+        let _ = job.reply.send(result);
+    }
+}
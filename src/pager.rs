@@ -0,0 +1,1510 @@
+use crate::follow::{Appended, Tail};
+use crate::hex::{self, HexFile};
+use crate::nvim::{FileOpts, Pool};
+use crate::range::{self, LineRange};
+use crate::screen::Screen;
+use crate::{
+    MAX_HIGHLIGHT_SIZE, ansi, emphasis_bg, highlight_batch, highlight_or_raw, split_chunks, style,
+    theme_colors, util, with_detected_filetype,
+};
+use crossterm::{
+    cursor,
+    event::{
+        self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind, KeyModifiers,
+        MouseButton, MouseEventKind,
+    },
+    execute, queue,
+    style::Stylize,
+    terminal::{self, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use regex::{Regex, RegexBuilder};
+use std::borrow::Cow;
+use std::cell::OnceCell;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+
+/// Drawn over search matches when the theme has no `Search` colors.
+const DEFAULT_MATCH_STYLE: &str = "\x1b[7m";
+/// Background of a clicked line when the theme has no `Visual` colors.
+const DEFAULT_FOCUS_BG: &str = "\x1b[48;5;239m";
+/// Rows one turn of the mouse wheel scrolls.
+const WHEEL_ROWS: usize = 3;
+/// How often the files in the pager are checked for changes on disk.
+const CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+pub enum PagerMsg {
+    /// Chunk index, the file line number of its first line, and its lines.
+    Chunk(usize, usize, Vec<String>),
+    Error(String),
+    Done,
+    /// A followed file got shorter than what was shown of it.
+    Truncated,
+}
+
+/// A `/` or `?` search over the lines loaded so far, with their escape
+/// sequences stripped.
+struct Search {
+    regex: Regex,
+    backward: bool,
+    /// Indices of the matching lines among the first `scanned`.
+    hits: Vec<usize>,
+    scanned: usize,
+}
+
+impl Search {
+    /// Smart case: the pattern ignores case unless it has an uppercase letter.
+    fn new(pattern: &str, backward: bool) -> Result<Search, regex::Error> {
+        let regex = RegexBuilder::new(pattern)
+            .case_insensitive(!pattern.chars().any(char::is_uppercase))
+            .build()?;
+        Ok(Search {
+            regex,
+            backward,
+            hits: Vec::new(),
+            scanned: 0,
+        })
+    }
+
+    /// Looks for matches in lines that arrived since the last scan.
+    fn scan(&mut self, lines: &[String]) {
+        for (i, line) in lines.iter().enumerate().skip(self.scanned) {
+            if self.regex.is_match(&ansi::strip(line)) {
+                self.hits.push(i);
+            }
+        }
+        self.scanned = lines.len();
+    }
+
+    /// The first hit at or after `from`, or going backward, the last one
+    /// before it.
+    fn find(&self, from: usize, backward: bool) -> Option<usize> {
+        let at = self.hits.partition_point(|&h| h < from);
+        if backward {
+            at.checked_sub(1).map(|i| self.hits[i])
+        } else {
+            self.hits.get(at).copied()
+        }
+    }
+
+    /// Forgets the hits, to scan another file's lines.
+    fn reset(&mut self) {
+        self.hits.clear();
+        self.scanned = 0;
+    }
+
+    /// `line` with every match painted in `style`.
+    fn paint(&self, line: &str, style: &str) -> String {
+        let spans: Vec<_> = self
+            .regex
+            .find_iter(&ansi::strip(line))
+            .map(|m| m.range())
+            .collect();
+        if spans.is_empty() {
+            return line.to_string();
+        }
+        ansi::overlay(line, &spans, style)
+    }
+}
+
+/// The top of the view: a line, and when wrapping, a row within it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+struct Pos {
+    line: usize,
+    row: usize,
+}
+
+/// How the loaded lines fill the screen, `width` columns right of the gutter.
+/// A dump takes the place of the lines, one row of bytes each, and never
+/// wraps.
+struct View<'a> {
+    lines: &'a [String],
+    hex: Option<&'a HexFile>,
+    wrap: bool,
+    width: usize,
+}
+
+impl View<'_> {
+    fn len(&self) -> usize {
+        match self.hex {
+            Some(h) => h.rows(),
+            None => self.lines.len(),
+        }
+    }
+
+    /// Line `i` as it is drawn.
+    fn line(&self, i: usize) -> Cow<'_, str> {
+        match self.hex {
+            Some(h) => Cow::Owned(h.row(i)),
+            None => Cow::Borrowed(&self.lines[i]),
+        }
+    }
+
+    /// Rows line `i` takes on screen; one if it isn't loaded, as before the
+    /// first chunk or right after a reload.
+    fn rows(&self, i: usize) -> usize {
+        match self.lines.get(i) {
+            Some(line) if self.wrap => ansi::rows(line, self.width),
+            _ => 1,
+        }
+    }
+
+    fn down(&self, mut pos: Pos, n: usize) -> Pos {
+        let mut left = n;
+        while left > 0 {
+            let step = (self.rows(pos.line) - 1).saturating_sub(pos.row).min(left);
+            pos.row += step;
+            left -= step;
+            if left == 0 || pos.line + 1 >= self.len() {
+                break;
+            }
+            pos = Pos {
+                line: pos.line + 1,
+                row: 0,
+            };
+            left -= 1;
+        }
+        pos
+    }
+
+    fn up(&self, mut pos: Pos, n: usize) -> Pos {
+        let mut left = n;
+        while left > 0 {
+            let step = pos.row.min(left);
+            pos.row -= step;
+            left -= step;
+            if left == 0 || pos.line == 0 {
+                break;
+            }
+            pos.line -= 1;
+            pos.row = self.rows(pos.line) - 1;
+            left -= 1;
+        }
+        pos
+    }
+
+    /// The furthest the view scrolls with `height` rows on screen.
+    fn bottom(&self, height: usize) -> Pos {
+        match self.len().checked_sub(1) {
+            Some(last) => {
+                let end = Pos {
+                    line: last,
+                    row: self.rows(last) - 1,
+                };
+                self.up(end, height.saturating_sub(1))
+            }
+            None => Pos::default(),
+        }
+    }
+
+    /// `pos`, `n` rows further down but not past the bottom.
+    fn scroll_down(&self, pos: Pos, n: usize, height: usize) -> Pos {
+        self.down(pos, n).min(self.bottom(height))
+    }
+
+    /// The view with line `i` at the top, or as close as it gets.
+    fn show(&self, i: usize, height: usize) -> Pos {
+        Pos { line: i, row: 0 }.min(self.bottom(height))
+    }
+
+    /// Keeps `pos` inside the file after the lines or the width changed.
+    fn clamp(&self, pos: Pos, height: usize) -> Pos {
+        if pos.line >= self.len() {
+            return self.bottom(height);
+        }
+        let row = pos.row.min(self.rows(pos.line) - 1);
+        Pos { row, ..pos }.min(self.bottom(height))
+    }
+}
+
+/// Input being typed on the status line.
+struct Prompt {
+    kind: PromptKind,
+    input: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PromptKind {
+    Search { backward: bool },
+    Command,
+}
+
+impl Prompt {
+    fn new(kind: PromptKind) -> Prompt {
+        Prompt {
+            kind,
+            input: String::new(),
+        }
+    }
+
+    fn sigil(&self) -> char {
+        match self.kind {
+            PromptKind::Search { backward: false } => '/',
+            PromptKind::Search { backward: true } => '?',
+            PromptKind::Command => ':',
+        }
+    }
+}
+
+/// Where a jump lands, which may not have loaded yet.
+#[derive(Debug, Clone, Copy)]
+enum Target {
+    /// A line number in the file.
+    Line(usize),
+    /// An index into the lines shown.
+    Index(usize),
+}
+
+impl Target {
+    /// The target's index among the `loaded` lines, or `None` while it is
+    /// still to come.
+    fn resolve(self, line_numbers: &[usize], loaded: usize, finished: bool) -> Option<usize> {
+        let i = match self {
+            Target::Line(n) => line_numbers.partition_point(|&l| l < n),
+            Target::Index(i) => i,
+        };
+        if i < loaded {
+            Some(i)
+        } else if finished {
+            Some(loaded.saturating_sub(1))
+        } else {
+            None
+        }
+    }
+}
+
+/// Positions saved with `m`, and the one before the last jump, for `''`.
+#[derive(Default)]
+struct Marks {
+    letters: HashMap<char, Pos>,
+    previous: Option<Pos>,
+}
+
+impl Marks {
+    fn jump(&mut self, top: &mut Pos, to: Pos) {
+        if *top != to {
+            self.previous = Some(*top);
+        }
+        *top = to;
+    }
+}
+
+/// Lines the pager will show once loading is done, for `%` jumps before then.
+fn expected_lines(path: &Path, line_ranges: &[LineRange]) -> usize {
+    let total = util::count_lines(path).unwrap_or(0);
+    if line_ranges.is_empty() {
+        return total;
+    }
+    range::resolve(line_ranges, total)
+        .iter()
+        .map(|r| r.len())
+        .sum()
+}
+
+/// A file for the pager to show.
+pub struct Page {
+    pub path: PathBuf,
+    pub name: String,
+    pub opts: FileOpts,
+    /// False for standard input, which only exists as a spooled copy.
+    pub editable: bool,
+    /// Show a dump even if the file isn't binary.
+    pub hex: bool,
+}
+
+/// How every file in the session is loaded.
+struct Settings<'a> {
+    pool: &'static Pool,
+    force_color: bool,
+    line_ranges: &'a [LineRange],
+    highlight_lines: &'a [LineRange],
+    follow: bool,
+}
+
+impl Settings<'_> {
+    /// Starts loading the first `len` bytes of `path` in the background.
+    fn load(&self, path: &Path, len: u64, opts: &FileOpts) -> mpsc::Receiver<PagerMsg> {
+        let (tx, rx) = mpsc::channel();
+        let path = path.to_path_buf();
+        let opts = opts.clone();
+        let ranges = self.line_ranges.to_vec();
+        let (pool, force_color) = (self.pool, self.force_color);
+        thread::spawn(move || {
+            let _ = load_file_parallel(&path, len, pool, force_color, &opts, &ranges, tx);
+        });
+        rx
+    }
+
+    /// Starts sending the lines appended to `path` after the first `offset`
+    /// bytes, once they are complete.
+    fn tail(
+        &self,
+        path: &Path,
+        opts: &FileOpts,
+        offset: u64,
+    ) -> io::Result<mpsc::Receiver<PagerMsg>> {
+        if util::is_binary_or_device(path)? {
+            return Err(io::Error::other("cannot follow a binary file"));
+        }
+        let tail = Tail::open(path, offset)?;
+        let (tx, rx) = mpsc::channel();
+        let path = path.to_path_buf();
+        let opts = opts.clone();
+        let pool = self.pool;
+        let highlight = offset <= MAX_HIGHLIGHT_SIZE || self.force_color;
+        thread::spawn(move || follow_file(&path, tail, pool, highlight, &opts, tx));
+        Ok(rx)
+    }
+
+    /// Line ranges of `path` to draw with the emphasis background.
+    fn emphasized(&self, path: &Path) -> io::Result<Vec<Range<usize>>> {
+        if self.highlight_lines.is_empty() {
+            return Ok(Vec::new());
+        }
+        Ok(range::resolve(
+            self.highlight_lines,
+            util::count_lines(path)?,
+        ))
+    }
+}
+
+/// What changes when a file is rewritten: its mtime, and on unix, its inode
+/// when another file is moved over it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Stamp {
+    modified: Option<SystemTime>,
+    inode: u64,
+}
+
+impl Stamp {
+    fn of(meta: &fs::Metadata) -> Stamp {
+        #[cfg(unix)]
+        let inode = meta.ino();
+        #[cfg(not(unix))]
+        let inode = 0;
+        Stamp {
+            modified: meta.modified().ok(),
+            inode,
+        }
+    }
+}
+
+/// One file in the pager session: its lines as they load, and where it was
+/// left.
+struct Doc {
+    path: PathBuf,
+    name: String,
+    opts: FileOpts,
+    editable: bool,
+    force_hex: bool,
+    /// The file as a dump, shown instead of `lines`.
+    hex: Option<HexFile>,
+    rx: mpsc::Receiver<PagerMsg>,
+    /// Bytes of the file when loading started, where following picks up.
+    loaded_len: u64,
+    stamp: Stamp,
+    /// Lines appended since, once following has started.
+    tail: Option<mpsc::Receiver<PagerMsg>>,
+    /// Whether the view sticks to the bottom as lines are appended.
+    following: bool,
+    /// Set when the file changed under the lines shown, to load it again.
+    stale: bool,
+    lines: Vec<String>,
+    line_numbers: Vec<usize>,
+    chunks_buffer: BTreeMap<usize, (usize, Vec<String>)>,
+    next_chunk_idx: usize,
+    finished_loading: bool,
+    gutter_width: usize,
+    top: Pos,
+    marks: Marks,
+    /// Line ranges drawn with the emphasis background.
+    emphasized: Vec<Range<usize>>,
+    /// Line number to center once it has loaded, until a key is pressed.
+    center_on: Option<usize>,
+    /// Line number to put back at the top after a reload.
+    anchor: Option<usize>,
+    /// Line last clicked on.
+    focus: Option<usize>,
+    expected_total: OnceCell<usize>,
+}
+
+impl Doc {
+    /// Starts loading `page` in the background.
+    fn open(page: Page, settings: &Settings) -> io::Result<Doc> {
+        let hex = open_hex(&page.path, page.hex)?;
+        let emphasized = match hex {
+            Some(_) => Vec::new(),
+            None => settings.emphasized(&page.path)?,
+        };
+        let meta = fs::metadata(&page.path)?;
+        let loaded_len = meta.len();
+        let tail = (settings.follow && page.editable && hex.is_none())
+            .then(|| settings.tail(&page.path, &page.opts, loaded_len).ok())
+            .flatten();
+        Ok(Doc {
+            rx: match hex {
+                Some(_) => mpsc::channel().1,
+                None => settings.load(&page.path, loaded_len, &page.opts),
+            },
+            finished_loading: hex.is_some(),
+            gutter_width: hex.as_ref().map_or(4, HexFile::offset_width),
+            hex,
+            force_hex: page.hex,
+            loaded_len,
+            stamp: Stamp::of(&meta),
+            following: tail.is_some(),
+            tail,
+            stale: false,
+            path: page.path,
+            name: page.name,
+            opts: page.opts,
+            editable: page.editable,
+            lines: Vec::new(),
+            line_numbers: Vec::new(),
+            chunks_buffer: BTreeMap::new(),
+            next_chunk_idx: 0,
+            top: Pos::default(),
+            marks: Marks::default(),
+            center_on: emphasized.first().map(|r| r.start + 1),
+            anchor: None,
+            emphasized,
+            focus: None,
+            expected_total: OnceCell::new(),
+        })
+    }
+
+    /// Loads the file again, to come back to the line now at the top.
+    fn reload(&mut self, settings: &Settings) -> io::Result<()> {
+        let meta = fs::metadata(&self.path)?;
+        let hex = open_hex(&self.path, self.force_hex)?;
+        self.emphasized = match hex {
+            Some(_) => Vec::new(),
+            None => settings.emphasized(&self.path)?,
+        };
+        self.anchor = self.line_numbers.get(self.top.line).copied();
+        self.loaded_len = meta.len();
+        self.stamp = Stamp::of(&meta);
+        self.rx = match hex {
+            Some(_) => mpsc::channel().1,
+            None => settings.load(&self.path, self.loaded_len, &self.opts),
+        };
+        if let Some(h) = &hex {
+            self.gutter_width = h.offset_width();
+        }
+        if self.tail.is_some() {
+            self.tail = settings.tail(&self.path, &self.opts, self.loaded_len).ok();
+        }
+        self.lines.clear();
+        self.line_numbers.clear();
+        self.chunks_buffer.clear();
+        self.next_chunk_idx = 0;
+        self.finished_loading = hex.is_some();
+        self.hex = hex;
+        self.center_on = None;
+        self.focus = None;
+        self.expected_total = OnceCell::new();
+        Ok(())
+    }
+
+    /// Whether the file was rewritten since it was loaded. A followed file
+    /// only counts when it is replaced, since its tail reads what is
+    /// appended, and notices a truncation.
+    fn changed_on_disk(&self) -> bool {
+        let Ok(meta) = fs::metadata(&self.path) else {
+            return false;
+        };
+        let now = Stamp::of(&meta);
+        match self.tail {
+            Some(_) => now.inode != self.stamp.inode,
+            None => now != self.stamp,
+        }
+    }
+
+    /// Lines there are, or will be once loading is done.
+    fn total(&self, line_ranges: &[LineRange]) -> usize {
+        if let Some(h) = &self.hex {
+            return h.rows();
+        }
+        if self.finished_loading {
+            return self.lines.len();
+        }
+        *self
+            .expected_total
+            .get_or_init(|| expected_lines(&self.path, line_ranges))
+    }
+
+    /// Takes in what the loader sent since the last call; true if anything
+    /// on screen may have changed.
+    fn receive(&mut self) -> bool {
+        let mut changed = false;
+        loop {
+            match self.rx.try_recv() {
+                Ok(PagerMsg::Chunk(idx, first, data)) => {
+                    self.chunks_buffer.insert(idx, (first, data));
+                    while let Some((first, chunk)) = self.chunks_buffer.remove(&self.next_chunk_idx)
+                    {
+                        self.line_numbers.extend(first..first + chunk.len());
+                        self.lines.extend(chunk);
+                        self.next_chunk_idx += 1;
+                        changed = true;
+                    }
+                }
+                Ok(PagerMsg::Error(e)) => {
+                    self.lines.push(format!("\x1b[31mError: {}\x1b[0m", e));
+                    self.finished_loading = true;
+                    changed = true;
+                }
+                Ok(PagerMsg::Done) => {
+                    self.finished_loading = true;
+                    changed = true;
+                }
+                Ok(PagerMsg::Truncated) => self.stale = true,
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    self.finished_loading = true;
+                    break;
+                }
+            }
+        }
+        // Appended lines go after all the others.
+        if self.finished_loading
+            && let Some(tail) = &self.tail
+        {
+            while let Ok(msg) = tail.try_recv() {
+                match msg {
+                    PagerMsg::Chunk(_, first, data) => {
+                        // A line that was unfinished when loaded comes again, whole.
+                        if self.line_numbers.last() == Some(&first) {
+                            self.line_numbers.pop();
+                            self.lines.pop();
+                        }
+                        self.line_numbers.extend(first..first + data.len());
+                        self.lines.extend(data);
+                        changed = true;
+                    }
+                    PagerMsg::Error(e) => {
+                        self.lines.push(format!("\x1b[31mError: {}\x1b[0m", e));
+                        changed = true;
+                    }
+                    PagerMsg::Truncated => self.stale = true,
+                    PagerMsg::Done => {}
+                }
+            }
+        }
+        if changed {
+            let last = self.line_numbers.last().copied().unwrap_or(1);
+            let digits = last.max(1).ilog10() as usize + 1;
+            self.gutter_width = self.gutter_width.max(digits);
+        }
+        changed
+    }
+}
+
+pub fn run_tui_pager(
+    pages: Vec<Page>,
+    pool: &'static Pool,
+    force_color: bool,
+    line_ranges: &[LineRange],
+    highlight_lines: &[LineRange],
+    follow: bool,
+    mut mouse: bool,
+) -> io::Result<()> {
+    let emphasis = if highlight_lines.is_empty() {
+        String::new()
+    } else {
+        emphasis_bg(theme_colors(pool))
+    };
+    let settings = Settings {
+        pool,
+        force_color,
+        line_ranges,
+        highlight_lines,
+        follow,
+    };
+    let mut docs = pages
+        .into_iter()
+        .map(|page| Doc::open(page, &settings))
+        .collect::<io::Result<Vec<_>>>()?;
+    let docs_len = docs.len();
+    let mut cur = 0;
+
+    terminal::enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen, cursor::Hide)?;
+    if mouse {
+        execute!(stdout, EnableMouseCapture)?;
+    }
+
+    let mut wrap = true;
+    // First column shown when long lines are chopped.
+    let mut x_offset: usize = 0;
+    let mut term_size = terminal::size()?;
+    let mut term_cols = term_size.0 as usize;
+    let mut term_rows = term_size.1 as usize;
+    let mut content_height = term_rows.saturating_sub(1);
+    let mut redraw = true;
+    let mut screen = Screen::new();
+    let mut spinner_idx = 0;
+    let mut tick_count = 0;
+    // The line on each content row, as last drawn, for clicks.
+    let mut row_lines: Vec<usize> = Vec::new();
+    let mut focus_style: Option<String> = None;
+
+    let mut search: Option<Search> = None;
+    let mut last_pattern = String::new();
+    let mut prompt: Option<Prompt> = None;
+    // Line of the match last jumped to; `n` and `N` move on from there.
+    let mut current_match: Option<usize> = None;
+    // A forward search that found nothing yet, retried as lines arrive.
+    let mut waiting_from: Option<usize> = None;
+    let mut message: Option<String> = None;
+    let mut match_style: Option<String> = None;
+
+    // The count typed before a command, and `m` or `'` waiting for a letter.
+    let mut count: Option<usize> = None;
+    let mut awaiting_mark: Option<char> = None;
+    let mut pending_jump: Option<Target> = None;
+    // Another file to show: the next one, or with `false`, the previous one.
+    let mut switch: Option<bool> = None;
+    let mut open_editor = false;
+    let mut last_check = Instant::now();
+
+    'pager: loop {
+        let check = last_check.elapsed() >= CHECK_INTERVAL;
+        if check {
+            last_check = Instant::now();
+        }
+        for (i, doc) in docs.iter_mut().enumerate() {
+            if check && doc.editable && doc.changed_on_disk() {
+                doc.stale = true;
+            }
+            if doc.stale {
+                doc.stale = false;
+                let reloaded = doc.reload(&settings);
+                if i == cur {
+                    pending_jump = None;
+                    current_match = None;
+                    waiting_from = None;
+                    if let Some(s) = &mut search {
+                        s.reset();
+                    }
+                    message = Some(match reloaded {
+                        Ok(()) => "Reloaded".into(),
+                        Err(e) => format!("Cannot reload: {}", e),
+                    });
+                    redraw = true;
+                }
+            }
+            if doc.receive() && i == cur {
+                redraw = true;
+            }
+        }
+        let doc = &mut docs[cur];
+
+        let text_width = term_cols.saturating_sub(doc.gutter_width + 3).max(1);
+        let view = View {
+            lines: &doc.lines,
+            hex: doc.hex.as_ref(),
+            wrap: wrap && doc.hex.is_none(),
+            width: text_width,
+        };
+        if let Some(target) = doc.center_on {
+            let pos = doc.line_numbers.partition_point(|&n| n < target);
+            if pos < doc.lines.len() {
+                doc.top = view.up(Pos { line: pos, row: 0 }, content_height / 2);
+                doc.center_on = None;
+                redraw = true;
+            }
+        }
+        // Waits for a screenful past the line, so the view doesn't end early.
+        if let Some(target) = doc.anchor {
+            let pos = doc.line_numbers.partition_point(|&n| n < target);
+            if doc.finished_loading || pos + content_height < doc.lines.len() {
+                doc.top = view.show(pos, content_height);
+                doc.anchor = None;
+                redraw = true;
+            }
+        }
+        if doc.following {
+            doc.top = view.bottom(content_height);
+        }
+
+        if let Some(s) = &mut search {
+            s.scan(&doc.lines);
+            if let Some(from) = waiting_from {
+                if let Some(hit) = s.find(from, false) {
+                    doc.marks.jump(&mut doc.top, view.show(hit, content_height));
+                    current_match = Some(hit);
+                    waiting_from = None;
+                    message = None;
+                    redraw = true;
+                } else if doc.finished_loading {
+                    waiting_from = None;
+                    message = Some("Pattern not found".into());
+                    redraw = true;
+                }
+            }
+        }
+
+        // Keys that piled up, like a held-down `j`, all go into one frame.
+        // A switch to another file leaves the rest for that file.
+        let mut timeout = Duration::from_millis(16);
+        while switch.is_none() && !open_editor && event::poll(timeout)? {
+            timeout = Duration::ZERO;
+            let key = match event::read()? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => key,
+                Event::Mouse(m) => {
+                    let (x, y) = (m.column as usize, m.row as usize);
+                    match m.kind {
+                        MouseEventKind::ScrollDown => {
+                            doc.top = view.scroll_down(doc.top, WHEEL_ROWS, content_height);
+                        }
+                        MouseEventKind::ScrollUp => doc.top = view.up(doc.top, WHEEL_ROWS),
+                        // The status bar stands for the whole file.
+                        MouseEventKind::Down(MouseButton::Left)
+                        | MouseEventKind::Drag(MouseButton::Left)
+                            if y == content_height =>
+                        {
+                            let total = doc.total(line_ranges);
+                            let target = Target::Index(x * total / term_cols.max(1));
+                            let loaded = view.len();
+                            match target.resolve(&doc.line_numbers, loaded, doc.finished_loading) {
+                                // Only the start of a drag is a jump to come back from.
+                                Some(i) if matches!(m.kind, MouseEventKind::Drag(_)) => {
+                                    doc.top = view.show(i, content_height);
+                                }
+                                _ => pending_jump = Some(target),
+                            }
+                        }
+                        MouseEventKind::Down(MouseButton::Left) => match row_lines.get(y) {
+                            Some(&i) if doc.focus != Some(i) => {
+                                doc.focus = Some(i);
+                                let n = doc.line_numbers.get(i).copied().unwrap_or(i + 1);
+                                message = Some(format!("Line {}", n));
+                            }
+                            _ => {
+                                doc.focus = None;
+                                message = None;
+                            }
+                        },
+                        _ => continue,
+                    }
+                    doc.center_on = None;
+                    doc.anchor = None;
+                    redraw = true;
+                    continue;
+                }
+                _ => continue,
+            };
+            doc.center_on = None;
+            doc.anchor = None;
+            redraw = true;
+            if let Some(p) = &mut prompt {
+                match key.code {
+                    KeyCode::Enter if p.kind == PromptKind::Command => {
+                        let input = p.input.trim().to_string();
+                        prompt = None;
+                        match input.as_str() {
+                            "" => {}
+                            "n" => switch = Some(true),
+                            "p" => switch = Some(false),
+                            _ => match input.parse::<usize>() {
+                                Ok(n) => pending_jump = Some(Target::Line(n)),
+                                Err(_) => {
+                                    message = Some(format!("Unknown command: :{}", input));
+                                }
+                            },
+                        }
+                    }
+                    KeyCode::Enter => {
+                        let p = prompt.take().unwrap_or_else(|| unreachable!());
+                        let PromptKind::Search { backward } = p.kind else {
+                            unreachable!()
+                        };
+                        if !p.input.is_empty() {
+                            last_pattern = p.input;
+                        }
+                        message = None;
+                        waiting_from = None;
+                        let started = (!last_pattern.is_empty())
+                            .then(|| Search::new(&last_pattern, backward));
+                        match started {
+                            None => {}
+                            Some(Ok(mut s)) => {
+                                current_match = None;
+                                s.scan(&doc.lines);
+                                match_style.get_or_insert_with(|| {
+                                    theme_colors(pool)
+                                        .colors("Search")
+                                        .unwrap_or_else(|| DEFAULT_MATCH_STYLE.to_string())
+                                });
+                                match s.find(doc.top.line, backward) {
+                                    Some(hit) => {
+                                        doc.marks
+                                            .jump(&mut doc.top, view.show(hit, content_height));
+                                        current_match = Some(hit);
+                                    }
+                                    None if !backward && !doc.finished_loading => {
+                                        waiting_from = Some(doc.top.line);
+                                        message = Some("Searching...".into());
+                                    }
+                                    None => message = Some("Pattern not found".into()),
+                                }
+                                search = Some(s);
+                            }
+                            Some(Err(e)) => {
+                                let reason = e.to_string();
+                                let reason = reason.lines().last().unwrap_or_default();
+                                let reason = reason.trim().trim_start_matches("error: ");
+                                message = Some(format!("Invalid pattern: {}", reason));
+                            }
+                        }
+                    }
+                    KeyCode::Esc => prompt = None,
+                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        prompt = None;
+                    }
+                    KeyCode::Backspace if p.input.is_empty() => prompt = None,
+                    KeyCode::Backspace => {
+                        p.input.pop();
+                    }
+                    KeyCode::Char(c) => p.input.push(c),
+                    _ => {}
+                }
+            } else if let Some(kind) = awaiting_mark.take() {
+                let marks = &mut doc.marks;
+                match key.code {
+                    KeyCode::Char(c) if kind == 'm' && c.is_ascii_alphabetic() => {
+                        marks.letters.insert(c, doc.top);
+                        message = Some(format!("Mark '{}' set", c));
+                    }
+                    KeyCode::Char('\'') if kind == '\'' => match marks.previous {
+                        Some(to) => marks.jump(&mut doc.top, view.clamp(to, content_height)),
+                        None => message = Some("No previous position".into()),
+                    },
+                    KeyCode::Char(c) if c.is_ascii_alphabetic() => match marks.letters.get(&c) {
+                        Some(&to) => marks.jump(&mut doc.top, view.clamp(to, content_height)),
+                        None => message = Some(format!("Mark '{}' not set", c)),
+                    },
+                    _ => {}
+                }
+            } else {
+                message = None;
+                let n = count.take();
+                let top = &mut doc.top;
+                match key.code {
+                    KeyCode::Char(c @ '0'..='9') => {
+                        let digit = c as usize - '0' as usize;
+                        count = Some(n.unwrap_or(0).saturating_mul(10).saturating_add(digit));
+                    }
+                    // Esc drops a count before it quits.
+                    KeyCode::Esc if n.is_some() => {}
+                    KeyCode::Char('q') | KeyCode::Esc => break 'pager,
+                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        break 'pager;
+                    }
+                    KeyCode::Char('/') | KeyCode::Char('?') if view.hex.is_some() => {
+                        message = Some("Search doesn't work in the hex view".into());
+                    }
+                    KeyCode::Char('/') | KeyCode::Char('?') => {
+                        let backward = key.code == KeyCode::Char('?');
+                        prompt = Some(Prompt::new(PromptKind::Search { backward }));
+                    }
+                    KeyCode::Char(':') => prompt = Some(Prompt::new(PromptKind::Command)),
+                    KeyCode::Char(c @ ('m' | '\'')) => awaiting_mark = Some(c),
+                    KeyCode::Char(']') => switch = Some(true),
+                    KeyCode::Char('[') => switch = Some(false),
+                    KeyCode::Char('n') | KeyCode::Char('N') if search.is_none() => {
+                        message = Some("No previous search".into());
+                    }
+                    KeyCode::Char('n') | KeyCode::Char('N') => {
+                        let Some(s) = &search else { unreachable!() };
+                        // `N` searches the other way.
+                        let backward = s.backward != (key.code == KeyCode::Char('N'));
+                        let from = match current_match {
+                            Some(m) if backward => m,
+                            Some(m) => m + 1,
+                            None => top.line,
+                        };
+                        match s.find(from, backward) {
+                            Some(hit) => {
+                                doc.marks.jump(top, view.show(hit, content_height));
+                                current_match = Some(hit);
+                            }
+                            None if !backward && !doc.finished_loading => {
+                                waiting_from = Some(from);
+                                message = Some("Searching...".into());
+                            }
+                            None => message = Some("Pattern not found".into()),
+                        }
+                    }
+                    KeyCode::Char('j') | KeyCode::Down | KeyCode::Enter => {
+                        *top = view.scroll_down(*top, n.unwrap_or(1), content_height);
+                    }
+                    KeyCode::Char('k') | KeyCode::Up => {
+                        *top = view.up(*top, n.unwrap_or(1));
+                    }
+                    KeyCode::PageDown | KeyCode::Char(' ') => {
+                        *top = view.scroll_down(*top, n.unwrap_or(content_height), content_height);
+                    }
+                    KeyCode::PageUp | KeyCode::Char('b') => {
+                        *top = view.up(*top, n.unwrap_or(content_height));
+                    }
+                    KeyCode::Char('d') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        *top = view.scroll_down(*top, content_height / 2, content_height);
+                    }
+                    KeyCode::Char('u') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        *top = view.up(*top, content_height / 2);
+                    }
+                    KeyCode::Home | KeyCode::Char('g') | KeyCode::End | KeyCode::Char('G')
+                        if n.is_some() =>
+                    {
+                        pending_jump = n.map(Target::Line);
+                    }
+                    KeyCode::Home | KeyCode::Char('g') => {
+                        doc.marks.jump(top, Pos::default());
+                    }
+                    KeyCode::End | KeyCode::Char('G') => {
+                        doc.marks.jump(top, view.bottom(content_height));
+                    }
+                    KeyCode::Char('%') => {
+                        let total = doc.total(line_ranges);
+                        let percent = n.unwrap_or(0).min(100);
+                        pending_jump = Some(Target::Index(total * percent / 100));
+                    }
+                    // Repaint everything, in case something else wrote to
+                    // the terminal.
+                    KeyCode::Char('l') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        screen.invalidate();
+                    }
+                    KeyCode::Char('r') => screen.invalidate(),
+                    KeyCode::Char('R') => doc.stale = true,
+                    KeyCode::Char('v') if !doc.editable => {
+                        message = Some("Cannot edit standard input".into());
+                    }
+                    KeyCode::Char('v') => open_editor = true,
+                    KeyCode::Char('F') if doc.following => {
+                        doc.following = false;
+                        message = Some("Stopped following".into());
+                    }
+                    KeyCode::Char('F') if !doc.editable => {
+                        message = Some("Cannot follow standard input".into());
+                    }
+                    KeyCode::Char('F') => {
+                        let started = match doc.tail {
+                            Some(_) => Ok(()),
+                            None => settings
+                                .tail(&doc.path, &doc.opts, doc.loaded_len)
+                                .map(|rx| doc.tail = Some(rx)),
+                        };
+                        match started {
+                            Ok(()) => {
+                                doc.following = true;
+                                *top = view.bottom(content_height);
+                                message = Some("Following; press F to stop".into());
+                            }
+                            Err(e) => message = Some(format!("Cannot follow: {}", e)),
+                        }
+                    }
+                    // Off, the terminal selects text as usual.
+                    KeyCode::Char('M') => {
+                        mouse = !mouse;
+                        if mouse {
+                            execute!(stdout, EnableMouseCapture)?;
+                            message = Some("Mouse on".into());
+                        } else {
+                            execute!(stdout, DisableMouseCapture)?;
+                            message = Some("Mouse off; select text as usual".into());
+                        }
+                    }
+                    KeyCode::Char('w') => {
+                        wrap = !wrap;
+                        x_offset = 0;
+                        top.row = 0;
+                        message = Some(
+                            if wrap {
+                                "Wrapping long lines"
+                            } else {
+                                "Chopping long lines"
+                            }
+                            .into(),
+                        );
+                    }
+                    KeyCode::Left | KeyCode::Right if view.wrap => {
+                        message = Some("Lines are wrapped; press w to chop them".into());
+                    }
+                    KeyCode::Left => {
+                        x_offset = x_offset.saturating_sub(text_width / 2);
+                    }
+                    KeyCode::Right => {
+                        let end = (top.line + content_height).min(view.len());
+                        let widest = (top.line.min(end)..end)
+                            .map(|i| ansi::width(&view.line(i)))
+                            .max()
+                            .unwrap_or(0);
+                        if x_offset + text_width < widest {
+                            x_offset += text_width / 2;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        if let Some(target) = pending_jump {
+            // A dump has no line numbers; its rows count from 1.
+            let target = match target {
+                Target::Line(n) if view.hex.is_some() => Target::Index(n.saturating_sub(1)),
+                target => target,
+            };
+            match target.resolve(&doc.line_numbers, view.len(), doc.finished_loading) {
+                Some(i) => {
+                    doc.marks.jump(&mut doc.top, view.show(i, content_height));
+                    pending_jump = None;
+                    message = None;
+                }
+                None => message = Some("Loading...".into()),
+            }
+            redraw = true;
+        }
+
+        // Scrolling up leaves the bottom, and with it follow mode.
+        if doc.following && doc.top < view.bottom(content_height) {
+            doc.following = false;
+        }
+
+        if open_editor {
+            open_editor = false;
+            let doc = &mut docs[cur];
+            let line = doc.line_numbers.get(doc.top.line).copied().unwrap_or(1);
+            match edit(&mut stdout, &doc.path, line, mouse)? {
+                Ok(_) => doc.stale = true,
+                Err(e) => message = Some(format!("Could not run the editor: {}", e)),
+            }
+            screen.invalidate();
+            redraw = true;
+        }
+
+        if let Some(forward) = switch.take() {
+            let to = if forward {
+                cur + 1
+            } else {
+                cur.wrapping_sub(1)
+            };
+            if to < docs.len() {
+                cur = to;
+                x_offset = 0;
+                pending_jump = None;
+                current_match = None;
+                waiting_from = None;
+                row_lines.clear();
+                if let Some(s) = &mut search {
+                    s.reset();
+                }
+            } else {
+                message = Some(
+                    if forward {
+                        "No next file"
+                    } else {
+                        "No previous file"
+                    }
+                    .into(),
+                );
+            }
+        }
+        let doc = &mut docs[cur];
+
+        let new_size = terminal::size()?;
+        if new_size.1 as usize != term_rows || new_size.0 as usize != term_cols {
+            term_size = new_size;
+            term_cols = term_size.0 as usize;
+            term_rows = term_size.1 as usize;
+            content_height = term_rows.saturating_sub(1);
+            screen.invalidate();
+            redraw = true;
+        }
+
+        if !doc.finished_loading {
+            tick_count += 1;
+            if tick_count % 5 == 0 {
+                spinner_idx = (spinner_idx + 1) % 4;
+                redraw = true;
+            }
+        }
+
+        if redraw {
+            let gutter_width = doc.gutter_width;
+            let text_width = term_cols.saturating_sub(gutter_width + 3).max(1);
+            let view = View {
+                lines: &doc.lines,
+                hex: doc.hex.as_ref(),
+                wrap: wrap && doc.hex.is_none(),
+                width: text_width,
+            };
+            let top = view.clamp(doc.top, content_height);
+            doc.top = top;
+            let mut rows = Vec::with_capacity(content_height);
+            row_lines.clear();
+            let mut skip = top.row;
+            for i in top.line..view.len() {
+                if rows.len() >= content_height {
+                    break;
+                }
+                let line = view.line(i);
+                let line = match (&search, &match_style) {
+                    (Some(s), Some(style)) if view.hex.is_none() => s.paint(&line, style),
+                    _ => line.into_owned(),
+                };
+                let pieces = if view.wrap {
+                    let take = skip + content_height - rows.len();
+                    let mut pieces = ansi::cut(&line, 0, text_width, take);
+                    pieces.drain(..skip.min(pieces.len()));
+                    pieces
+                } else {
+                    ansi::cut(&line, x_offset, text_width, 1)
+                };
+                let background = if doc.focus == Some(i) {
+                    Some(&*focus_style.get_or_insert_with(|| {
+                        theme_colors(pool)
+                            .bg("Visual")
+                            .unwrap_or_else(|| DEFAULT_FOCUS_BG.to_string())
+                    }))
+                } else {
+                    let n = doc.line_numbers.get(i);
+                    n.is_some_and(|n| doc.emphasized.iter().any(|r| r.contains(&(n - 1))))
+                        .then_some(&emphasis)
+                };
+                for (k, mut piece) in pieces.into_iter().enumerate() {
+                    if let Some(background) = background {
+                        // Padded out, since erasing from the last column
+                        // would take its character with it.
+                        let pad = text_width.saturating_sub(ansi::width(&piece));
+                        piece.push_str(&" ".repeat(pad));
+                        piece = String::from_utf8_lossy(&style::emphasize(
+                            piece.as_bytes(),
+                            background,
+                        ))
+                        .into_owned();
+                    }
+                    let gutter = match doc.line_numbers.get(i) {
+                        _ if view.hex.is_some() => {
+                            let offset = i * hex::ROW_BYTES;
+                            format!("{:0>width$x} │ ", offset, width = gutter_width)
+                        }
+                        Some(n) if k + skip == 0 => {
+                            format!("{:>width$} │ ", n, width = gutter_width)
+                        }
+                        _ => format!("{:>width$} │ ", "", width = gutter_width),
+                    };
+                    rows.push(format!("{}{}", gutter.dark_grey(), piece));
+                    row_lines.push(i);
+                }
+                skip = 0;
+            }
+            rows.resize(content_height, String::new());
+
+            let status = match &prompt {
+                Some(p) => format!("{}{}", p.sigil(), p.input),
+                None => {
+                    let spinner = if doc.finished_loading {
+                        ""
+                    } else {
+                        match spinner_idx {
+                            0 => "|",
+                            1 => "/",
+                            2 => "-",
+                            _ => "\\",
+                        }
+                    };
+                    let percentage = if view.len() == 0 {
+                        0
+                    } else {
+                        (top.line * 100) / view.len()
+                    };
+                    let file = if docs_len > 1 {
+                        format!(" file {}/{} |", cur + 1, docs_len)
+                    } else {
+                        String::new()
+                    };
+                    let following = if doc.following { " following |" } else { "" };
+                    let column = if x_offset > 0 {
+                        format!(" col {} |", x_offset + 1)
+                    } else {
+                        String::new()
+                    };
+                    let pos_info = format!(
+                        "{}{}{} {}:{} | {}% {} ",
+                        file,
+                        following,
+                        column,
+                        top.line + 1,
+                        view.len(),
+                        percentage,
+                        spinner
+                    );
+                    let pending = match (count, awaiting_mark) {
+                        (Some(n), _) => Some(n.to_string()),
+                        (None, Some(c)) => Some(c.to_string()),
+                        (None, None) => None,
+                    };
+                    let status_left = match (&pending, &message) {
+                        (Some(p), _) => format!(" {} ", p),
+                        (None, Some(m)) => format!(" {} ", m),
+                        (None, None) => format!(" {} ", doc.name),
+                    };
+                    // A long name gives way to the position.
+                    let room = term_cols.saturating_sub(ansi::width(&pos_info));
+                    let status_left = ansi::cut(&status_left, 0, room, 1).remove(0);
+                    let padding_len = room.saturating_sub(ansi::width(&status_left));
+                    let bar = format!("{}{}{}", status_left, " ".repeat(padding_len), pos_info);
+                    bar.reverse().to_string()
+                }
+            };
+            let prompt_end = prompt.as_ref().map(|_| ansi::width(&status));
+            screen.draw(&mut stdout, rows, status, term_cols)?;
+            match prompt_end {
+                Some(x) => queue!(
+                    stdout,
+                    cursor::MoveTo(x as u16, content_height as u16),
+                    cursor::Show
+                )?,
+                None => queue!(stdout, cursor::Hide)?,
+            }
+            stdout.flush()?;
+            redraw = false;
+        }
+    }
+    if mouse {
+        execute!(stdout, DisableMouseCapture)?;
+    }
+    execute!(stdout, cursor::Show, LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()?;
+    Ok(())
+}
+
+/// The file as a dump, if it is binary or one is asked for.
+fn open_hex(path: &Path, force: bool) -> io::Result<Option<HexFile>> {
+    if force || util::is_binary_or_device(path).unwrap_or(false) {
+        return HexFile::open(path).map(Some);
+    }
+    Ok(None)
+}
+
+/// Sends what `tail` reads as chunks of lines, highlighted unless the file is
+/// too big to be, until the pager stops listening.
+fn follow_file(
+    path: &Path,
+    mut tail: Tail,
+    pool: &'static Pool,
+    highlight: bool,
+    opts: &FileOpts,
+    tx: mpsc::Sender<PagerMsg>,
+) {
+    let opts = &with_detected_filetype(path, pool, opts);
+    loop {
+        let msg = match tail.wait() {
+            Ok(Appended::Lines(batch)) => {
+                let data = if highlight {
+                    highlight_batch(path, &batch, pool, opts)
+                } else {
+                    Ok(util::skip_lines(&batch.content, batch.context).to_vec())
+                };
+                match data {
+                    Ok(data) => {
+                        let lines = String::from_utf8_lossy(&data)
+                            .lines()
+                            .map(|l| l.to_string())
+                            .collect();
+                        PagerMsg::Chunk(0, batch.first_line + 1, lines)
+                    }
+                    Err(e) => PagerMsg::Error(e.to_string()),
+                }
+            }
+            Ok(Appended::Truncated) => PagerMsg::Truncated,
+            Err(e) => PagerMsg::Error(e.to_string()),
+        };
+        let last = !matches!(msg, PagerMsg::Chunk(..));
+        if tx.send(msg).is_err() || last {
+            return;
+        }
+    }
+}
+
+/// Runs `$VISUAL` or `$EDITOR` (default `nvim`) on `path` at `line`, handing
+/// it the terminal until it exits. The outer error is the terminal's, the
+/// inner one the editor's.
+fn edit(
+    out: &mut impl Write,
+    path: &Path,
+    line: usize,
+    mouse: bool,
+) -> io::Result<io::Result<ExitStatus>> {
+    let editor = ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|e| !e.trim().is_empty())
+        .unwrap_or_else(|| "nvim".to_string());
+    // Like a shell would, so `EDITOR="code -w"` works.
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("nvim");
+
+    if mouse {
+        execute!(out, DisableMouseCapture)?;
+    }
+    execute!(out, cursor::Show, LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()?;
+    let status = Command::new(program)
+        .args(words)
+        .arg(format!("+{}", line))
+        .arg(path)
+        .status()
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", program, e)));
+    terminal::enable_raw_mode()?;
+    execute!(out, EnterAlternateScreen, cursor::Hide)?;
+    if mouse {
+        execute!(out, EnableMouseCapture)?;
+    }
+    Ok(status)
+}
+
+fn load_file_parallel(
+    path: &Path,
+    len: u64,
+    pool: &'static Pool,
+    force_color: bool,
+    opts: &FileOpts,
+    line_ranges: &[LineRange],
+    tx: mpsc::Sender<PagerMsg>,
+) -> io::Result<()> {
+    let file = File::open(path)?;
+
+    if util::is_binary_or_device(path).unwrap_or(false) {
+        let _ = tx.send(PagerMsg::Error("Binary/Device detected".into()));
+        let _ = tx.send(PagerMsg::Done);
+        return Ok(());
+    }
+
+    if len > MAX_HIGHLIGHT_SIZE && !force_color {
+        let selected = if line_ranges.is_empty() {
+            std::iter::once(0..usize::MAX).collect()
+        } else {
+            range::resolve(line_ranges, util::count_lines(path)?)
+        };
+        // The tail takes over at `len`.
+        let reader = BufReader::new(file.take(len));
+        let mut idx = 0;
+        let mut first = 0;
+        let mut chunk = Vec::new();
+        for (n, line) in reader.lines().enumerate() {
+            let Ok(l) = line else { continue };
+            if !selected.iter().any(|r| r.contains(&n)) {
+                continue;
+            }
+            // A chunk only ever holds consecutive lines.
+            if chunk.len() >= 1000 || (!chunk.is_empty() && first + chunk.len() != n + 1) {
+                let _ = tx.send(PagerMsg::Chunk(idx, first, std::mem::take(&mut chunk)));
+                idx += 1;
+            }
+            if chunk.is_empty() {
+                first = n + 1;
+            }
+            chunk.push(l);
+        }
+        if !chunk.is_empty() {
+            let _ = tx.send(PagerMsg::Chunk(idx, first, chunk));
+        }
+        let _ = tx.send(PagerMsg::Done);
+        return Ok(());
+    }
+
+    drop(file);
+    let opts = &with_detected_filetype(path, pool, opts);
+    let chunks = split_chunks(path, len, pool.max_workers(), line_ranges)?;
+
+    let mut handles = Vec::new();
+    for (i, chunk) in chunks.into_iter().enumerate() {
+        let t_opts = opts.clone();
+        let t_path = path.to_path_buf();
+        let thread_tx = tx.clone();
+        handles.push(thread::spawn(move || {
+            let res = highlight_or_raw(&t_path, &chunk.content, pool, &t_opts);
+            match res {
+                Ok(bytes) => {
+                    let lines: Vec<String> = String::from_utf8_lossy(&bytes)
+                        .lines()
+                        .skip(chunk.context)
+                        .map(|l| l.to_string())
+                        .collect();
+                    let _ = thread_tx.send(PagerMsg::Chunk(i, chunk.first_line + 1, lines));
+                }
+                Err(e) => {
+                    let _ = thread_tx.send(PagerMsg::Error(e.to_string()));
+                }
+            }
+        }));
+    }
+    for h in handles {
+        let _ = h.join();
+    }
+    let _ = tx.send(PagerMsg::Done);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn view(lines: &[String], wrap: bool) -> View<'_> {
+        View {
+            lines,
+            hex: None,
+            wrap,
+            width: 10,
+        }
+    }
+
+    #[test]
+    fn scrolling_an_empty_view_stays_at_the_top() {
+        for wrap in [true, false] {
+            let v = view(&[], wrap);
+            let top = Pos::default();
+            assert_eq!(v.down(top, 3), top);
+            assert_eq!(v.scroll_down(top, 3, 5), top);
+            assert_eq!(v.up(top, 3), top);
+            assert_eq!(v.bottom(5), top);
+            assert_eq!(v.show(7, 5), top);
+        }
+    }
+
+    #[test]
+    fn scrolling_from_past_the_loaded_lines() {
+        // Where a reload leaves the top while the file comes in again.
+        let lines = vec!["a".to_string(); 3];
+        let v = view(&lines, true);
+        let stale = Pos { line: 40, row: 2 };
+        assert_eq!(v.down(stale, 1), stale);
+        assert_eq!(v.up(stale, 3), Pos { line: 39, row: 0 });
+        assert_eq!(v.clamp(stale, 2), Pos { line: 1, row: 0 });
+    }
+
+    #[test]
+    fn wrapped_lines_scroll_by_row() {
+        let lines = vec!["x".repeat(25), "y".to_string(), "z".to_string()];
+        let v = view(&lines, true);
+        assert_eq!(v.rows(0), 3);
+        assert_eq!(v.down(Pos::default(), 2), Pos { line: 0, row: 2 });
+        assert_eq!(v.down(Pos::default(), 3), Pos { line: 1, row: 0 });
+        assert_eq!(v.up(Pos { line: 1, row: 0 }, 1), Pos { line: 0, row: 2 });
+        assert_eq!(v.bottom(3), Pos { line: 0, row: 2 });
+    }
+}
@@ -0,0 +1,162 @@
+use std::ops::Range;
+
+/// One end of a `--line-range`: a 1-based line number, or `-N` for the N-th
+/// line from the end.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LineBound {
+    Line(usize),
+    FromEnd(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RangeEnd {
+    At(LineBound),
+    /// `A:+N`, N lines after the start.
+    Plus(usize),
+}
+
+/// A `--line-range` as given on the command line: `A:B`, `A:`, `:B`, `A`
+/// or `A:+N`, where A and B may be `-N` to count from the end.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineRange {
+    start: LineBound,
+    end: RangeEnd,
+}
+
+pub fn parse_line_range(s: &str) -> Result<LineRange, String> {
+    let invalid = || format!("expected A:B, A:, :B, A or A:+N, got `{}`", s);
+    let bound = |v: &str| -> Result<LineBound, String> {
+        let (v, from_end) = match v.strip_prefix('-') {
+            Some(rest) => (rest, true),
+            None => (v, false),
+        };
+        match v.parse::<usize>() {
+            Ok(n) if n > 0 && from_end => Ok(LineBound::FromEnd(n)),
+            Ok(n) if n > 0 => Ok(LineBound::Line(n)),
+            _ => Err(invalid()),
+        }
+    };
+
+    let Some((start, end)) = s.split_once(':') else {
+        let line = bound(s)?;
+        return Ok(LineRange {
+            start: line,
+            end: RangeEnd::At(line),
+        });
+    };
+    let start = if start.is_empty() {
+        LineBound::Line(1)
+    } else {
+        bound(start)?
+    };
+    let end = if end.is_empty() {
+        RangeEnd::At(LineBound::FromEnd(1))
+    } else if let Some(count) = end.strip_prefix('+') {
+        RangeEnd::Plus(count.parse().map_err(|_| invalid())?)
+    } else {
+        RangeEnd::At(bound(end)?)
+    };
+    let reversed = match (start, end) {
+        (LineBound::Line(a), RangeEnd::At(LineBound::Line(b))) => b < a,
+        (LineBound::FromEnd(a), RangeEnd::At(LineBound::FromEnd(b))) => b > a,
+        _ => false,
+    };
+    if reversed {
+        return Err("the range ends before it starts".to_string());
+    }
+    Ok(LineRange { start, end })
+}
+
+/// Turns `ranges` into sorted, non-overlapping 0-based line index ranges for a
+/// file of `total` lines. Ranges that fall outside the file are dropped.
+pub fn resolve(ranges: &[LineRange], total: usize) -> Vec<Range<usize>> {
+    // A bound counted from the end may point before the first line.
+    let index = |b: LineBound| match b {
+        LineBound::Line(n) => Some(n - 1),
+        LineBound::FromEnd(n) => total.checked_sub(n),
+    };
+    let mut resolved: Vec<Range<usize>> = ranges
+        .iter()
+        .map(|r| {
+            let start = index(r.start).unwrap_or(0);
+            let end = match r.end {
+                RangeEnd::At(b) => index(b).map_or(0, |i| i + 1),
+                RangeEnd::Plus(n) => start.saturating_add(n).saturating_add(1),
+            };
+            start.min(total)..end.min(total)
+        })
+        .filter(|r| !r.is_empty())
+        .collect();
+    resolved.sort_by_key(|r| r.start);
+
+    let mut merged: Vec<Range<usize>> = Vec::new();
+    for r in resolved {
+        match merged.last_mut() {
+            Some(last) if r.start <= last.end => last.end = last.end.max(r.end),
+            _ => merged.push(r),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(specs: &[&str], total: usize) -> Vec<Range<usize>> {
+        let ranges: Vec<LineRange> = specs.iter().map(|s| parse_line_range(s).unwrap()).collect();
+        resolve(&ranges, total)
+    }
+
+    #[test]
+    fn parses_every_form() {
+        let range = |start, end| Ok(LineRange { start, end });
+        use LineBound::*;
+        use RangeEnd::*;
+        assert_eq!(parse_line_range("3:7"), range(Line(3), At(Line(7))));
+        assert_eq!(parse_line_range("3:"), range(Line(3), At(FromEnd(1))));
+        assert_eq!(parse_line_range(":7"), range(Line(1), At(Line(7))));
+        assert_eq!(parse_line_range("5"), range(Line(5), At(Line(5))));
+        assert_eq!(parse_line_range("3:+4"), range(Line(3), Plus(4)));
+        assert_eq!(parse_line_range("-20:"), range(FromEnd(20), At(FromEnd(1))));
+        assert_eq!(parse_line_range("2:-2"), range(Line(2), At(FromEnd(2))));
+    }
+
+    #[test]
+    fn rejects_bad_ranges() {
+        for s in [
+            "", "0", "0:3", "a:3", "3:b", "3:+x", "3:+-1", "--1", "3:2", "-2:-5",
+        ] {
+            assert!(parse_line_range(s).is_err(), "{:?} parsed", s);
+        }
+        // Only known to be reversed once the length is.
+        assert!(parse_line_range("-2:3").is_ok());
+    }
+
+    #[test]
+    fn resolves_against_the_length() {
+        assert_eq!(lines(&["3:7"], 10), vec![2..7]);
+        assert_eq!(lines(&["8:"], 10), vec![7..10]);
+        assert_eq!(lines(&[":2"], 10), vec![0..2]);
+        assert_eq!(lines(&["4"], 10), vec![3..4]);
+        assert_eq!(lines(&["-3:"], 10), vec![7..10]);
+        assert_eq!(lines(&["2:-2"], 10), vec![1..9]);
+        assert_eq!(lines(&["3:+2"], 10), vec![2..5]);
+    }
+
+    #[test]
+    fn merges_overlapping_and_touching_ranges() {
+        assert_eq!(lines(&["5:8", "1:2", "7:9"], 20), vec![0..2, 4..9]);
+        assert_eq!(lines(&["1:3", "4:5"], 20), vec![0..5]);
+    }
+
+    #[test]
+    fn clamps_to_the_file() {
+        assert_eq!(lines(&["8:20"], 10), vec![7..10]);
+        assert_eq!(lines(&["12:20"], 10), Vec::<Range<usize>>::new());
+        assert_eq!(lines(&["-20:"], 10), vec![0..10]);
+        assert_eq!(lines(&[":-20"], 10), Vec::<Range<usize>>::new());
+        assert_eq!(lines(&["5:+18446744073709551615"], 10), vec![4..10]);
+        assert_eq!(lines(&["3:7"], 0), Vec::<Range<usize>>::new());
+    }
+}
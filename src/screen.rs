@@ -0,0 +1,169 @@
+use crate::ansi;
+use crossterm::{
+    cursor, queue,
+    style::Print,
+    terminal::{self, ClearType},
+};
+use std::io::{self, Write};
+
+/// What the pager last put on the terminal, so that a redraw only sends the
+/// rows that changed. A scroll by a few rows becomes a scroll of the content
+/// region plus the rows that came into view.
+pub struct Screen {
+    rows: Vec<String>,
+    status: String,
+    cols: usize,
+    valid: bool,
+}
+
+impl Screen {
+    pub fn new() -> Screen {
+        Screen {
+            rows: Vec::new(),
+            status: String::new(),
+            cols: 0,
+            valid: false,
+        }
+    }
+
+    /// Forgets what is on the terminal, so the next frame is drawn in full.
+    pub fn invalidate(&mut self) {
+        self.valid = false;
+    }
+
+    /// Brings the terminal from the last frame to `rows` over the status line
+    /// `status`, each row at most `cols` wide.
+    pub fn draw(
+        &mut self,
+        out: &mut impl Write,
+        rows: Vec<String>,
+        status: String,
+        cols: usize,
+    ) -> io::Result<()> {
+        let height = rows.len();
+        if !self.valid || self.rows.len() != height || self.cols != cols {
+            queue!(out, terminal::Clear(ClearType::All))?;
+            self.rows = vec![String::new(); height];
+            self.status = String::new();
+            self.cols = cols;
+            self.valid = true;
+        }
+
+        let shift = self.best_shift(&rows);
+        if shift != 0 && height > 0 {
+            let n = shift.unsigned_abs();
+            // Only the content rows scroll; the status line stays put.
+            write!(out, "\x1b[1;{}r", height)?;
+            if shift > 0 {
+                queue!(out, terminal::ScrollUp(n as u16))?;
+                self.rows.drain(..n);
+                self.rows.resize(height, String::new());
+            } else {
+                queue!(out, terminal::ScrollDown(n as u16))?;
+                self.rows.truncate(height - n);
+                self.rows
+                    .splice(0..0, std::iter::repeat_n(String::new(), n));
+            }
+            write!(out, "\x1b[r")?;
+        }
+
+        for (y, row) in rows.iter().enumerate() {
+            if *row != self.rows[y] {
+                self.put(out, y, row)?;
+            }
+        }
+        if status != self.status {
+            self.put(out, height, &status)?;
+        }
+        self.rows = rows;
+        self.status = status;
+        Ok(())
+    }
+
+    /// Rewrites screen row `y`, clearing what's left of the old one.
+    fn put(&self, out: &mut impl Write, y: usize, row: &str) -> io::Result<()> {
+        queue!(out, cursor::MoveTo(0, y as u16), Print(row))?;
+        // Erasing from the last column would take its character with it.
+        if ansi::width(row) < self.cols {
+            queue!(out, terminal::Clear(ClearType::UntilNewLine))?;
+        }
+        Ok(())
+    }
+
+    /// How many rows the content moved up (or down, if negative) since the
+    /// last frame, when scrolling saves rewriting rows; otherwise 0.
+    fn best_shift(&self, rows: &[String]) -> isize {
+        let height = rows.len() as isize;
+        let changed = |shift: isize| {
+            (0..height)
+                .filter(|&y| {
+                    let row = &rows[y as usize];
+                    let old = y + shift;
+                    // Rows scrolled into view start out blank.
+                    if (0..height).contains(&old) {
+                        *row != self.rows[old as usize]
+                    } else {
+                        !row.is_empty()
+                    }
+                })
+                .count()
+        };
+        let mut best = (changed(0), 0);
+        for shift in (1..height).flat_map(|n| [n, -n]) {
+            // The scroll itself costs about as much as a row.
+            let cost = changed(shift) + 1;
+            if cost < best.0 {
+                best = (cost, shift);
+            }
+        }
+        best.1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(rows: &[&str]) -> Vec<String> {
+        rows.iter().map(|r| r.to_string()).collect()
+    }
+
+    fn screen(rows: &[&str]) -> Screen {
+        Screen {
+            rows: frame(rows),
+            status: String::new(),
+            cols: 10,
+            valid: true,
+        }
+    }
+
+    #[test]
+    fn a_one_line_scroll_is_a_shift_of_one() {
+        let screen = screen(&["a", "b", "c", "d", "e"]);
+        assert_eq!(screen.best_shift(&frame(&["b", "c", "d", "e", "f"])), 1);
+        assert_eq!(screen.best_shift(&frame(&["z", "a", "b", "c", "d"])), -1);
+    }
+
+    #[test]
+    fn a_full_change_is_not_scrolled() {
+        let screen = screen(&["a", "b", "c", "d", "e"]);
+        assert_eq!(screen.best_shift(&frame(&["v", "w", "x", "y", "z"])), 0);
+        assert_eq!(screen.best_shift(&frame(&["a", "b", "c", "d", "e"])), 0);
+    }
+
+    #[test]
+    fn a_scroll_redraws_only_the_row_that_came_into_view() {
+        let mut screen = Screen::new();
+        let mut out = Vec::new();
+        let first = frame(&["r1", "r2", "r3", "r4"]);
+        screen.draw(&mut out, first, "s".into(), 10).unwrap();
+        let mut out = Vec::new();
+        let next = frame(&["r2", "r3", "r4", "r5"]);
+        screen.draw(&mut out, next, "s".into(), 10).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("\x1b[1;4r"));
+        assert!(out.contains("r5"));
+        assert!(!out.contains("r2") && !out.contains("r3") && !out.contains("r4"));
+        assert!(!out.contains('s'));
+    }
+}
@@ -0,0 +1,183 @@
+use crate::cache;
+use crate::nvim::{self, NvimInfo, Probe};
+use crate::util;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+const STATE_HEADER: &str = "meow-nvim-info 1";
+const STATE_FILE: &str = "nvim_info";
+
+fn state_path() -> Option<PathBuf> {
+    Some(cache::cache_root()?.join(STATE_FILE))
+}
+
+/// What the last probe found, as long as the config, the packpath and the
+/// Neovim binary are unchanged, so most runs never load the user's config.
+pub fn load() -> Option<NvimInfo> {
+    let content = fs::read_to_string(state_path()?).ok()?;
+    let mut lines = content.lines();
+    if lines.next()? != STATE_HEADER {
+        return None;
+    }
+
+    let mut theme = None;
+    let mut rtp = None;
+    for line in lines {
+        let mut fields = line.split('\t');
+        match (fields.next()?, fields.next(), fields.next()) {
+            ("theme", Some(t), None) => theme = Some(t.to_string()),
+            ("rtp", Some(r), None) => rtp = Some(r.to_string()),
+            ("nvim", Some(fp), None) if fp != util::nvim_fingerprint(nvim::binary()) => {
+                return None;
+            }
+            ("tree", Some(p), Some(stamp)) if stamp != tree_stamp(Path::new(p)) => return None,
+            ("dir", Some(p), Some(stamp)) if stamp != dir_stamp(Path::new(p)) => return None,
+            _ => {}
+        }
+    }
+    Some(NvimInfo {
+        theme: theme?,
+        rtp: rtp?,
+    })
+}
+
+pub fn store(probe: &Probe) -> io::Result<()> {
+    let Some(path) = state_path() else {
+        return Ok(());
+    };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut out = Vec::new();
+    writeln!(out, "{}", STATE_HEADER)?;
+    writeln!(out, "theme\t{}", probe.info.theme)?;
+    writeln!(out, "rtp\t{}", probe.info.rtp)?;
+    writeln!(out, "nvim\t{}", util::nvim_fingerprint(nvim::binary()))?;
+    let config = Path::new(&probe.config_dir);
+    writeln!(out, "tree\t{}\t{}", config.display(), tree_stamp(config))?;
+    for dir in pack_dirs(&probe.packpath) {
+        writeln!(out, "dir\t{}\t{}", dir.display(), dir_stamp(&dir))?;
+    }
+
+    let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+    fs::write(&tmp, out)?;
+    fs::rename(&tmp, &path)
+}
+
+pub fn clear() -> io::Result<()> {
+    let Some(path) = state_path() else {
+        return Ok(());
+    };
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Every `pack` directory on the packpath plus its `start`/`opt` folders;
+/// installing or removing a plugin touches one of their mtimes.
+fn pack_dirs(packpath: &str) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    for pp in packpath.split(',').filter(|p| !p.is_empty()) {
+        let pack = Path::new(pp).join("pack");
+        if let Ok(entries) = fs::read_dir(&pack) {
+            for entry in entries.flatten() {
+                dirs.push(entry.path().join("start"));
+                dirs.push(entry.path().join("opt"));
+            }
+        }
+        dirs.push(pack);
+    }
+    dirs
+}
+
+fn mtime_nanos(path: &Path) -> Option<u128> {
+    fs::metadata(path)
+        .ok()?
+        .modified()
+        .ok()?
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_nanos())
+}
+
+fn dir_stamp(path: &Path) -> String {
+    mtime_nanos(path).map_or_else(|| "-".to_string(), |t| t.to_string())
+}
+
+/// The parts of a config directory that can change the theme or the
+/// runtimepath.
+const CONFIG_ENTRIES: &[&str] = &[
+    "init.lua",
+    "init.vim",
+    "lua",
+    "plugin",
+    "after",
+    "colors",
+    "lazy-lock.json",
+];
+
+/// Newest mtime among `CONFIG_ENTRIES` under `root`, so edits to `init.lua`
+/// as well as to modules under `lua/` are noticed without walking `.git` and
+/// the like on every run.
+fn tree_stamp(root: &Path) -> String {
+    fn newest(path: &Path, depth: usize) -> Option<u128> {
+        let own = mtime_nanos(path);
+        if depth == 0 || !path.is_dir() {
+            return own;
+        }
+        fs::read_dir(path)
+            .ok()?
+            .flatten()
+            .filter(|e| !e.file_name().to_string_lossy().starts_with('.'))
+            .filter_map(|e| newest(&e.path(), depth - 1))
+            .chain(own)
+            .max()
+    }
+    CONFIG_ENTRIES
+        .iter()
+        .filter_map(|entry| newest(&root.join(entry), 8))
+        .chain(mtime_nanos(root))
+        .max()
+        .map_or_else(|| "-".to_string(), |t| t.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::time::Duration;
+
+    fn touch(path: &Path, secs: u64) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let f = File::create(path).unwrap();
+        f.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+            .unwrap();
+    }
+
+    #[test]
+    fn tree_stamp_looks_only_at_the_config() {
+        let root = std::env::temp_dir().join(format!("meow-test-state-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        touch(&root.join("init.lua"), 1_000);
+        touch(&root.join("lua/plugins/theme.lua"), 2_000);
+        touch(&root.join(".git/objects/ab/cdef"), 3_000);
+        let before = tree_stamp(&root);
+
+        touch(&root.join(".git/objects/ab/cdef"), 9_000_000_000);
+        touch(&root.join("README.md"), 9_000_000_000);
+        // Adding files bumps the root's own mtime; pin it back.
+        File::open(&root)
+            .unwrap()
+            .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(500))
+            .unwrap();
+        assert_eq!(tree_stamp(&root), before);
+
+        touch(&root.join("lua/plugins/theme.lua"), 9_000_000_000);
+        assert_ne!(tree_stamp(&root), before);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,330 @@
+use clap::ValueEnum;
+use crossterm::terminal;
+use std::io::{self, IsTerminal, Write};
+use std::ops::Range;
+
+const MIN_NUMBER_WIDTH: usize = 4;
+const DEFAULT_TERM_WIDTH: usize = 80;
+const RESET: &str = "\x1b[0m";
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum StyleComponent {
+    Numbers,
+    Grid,
+    Header,
+    Rule,
+    Plain,
+}
+
+/// Decorations drawn around printed files.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Style {
+    pub numbers: bool,
+    pub grid: bool,
+    pub header: bool,
+    pub rule: bool,
+}
+
+impl Style {
+    /// Without `--style`, files only get a header when there are several of
+    /// them; `plain` turns everything off.
+    pub fn new(components: &[StyleComponent], multiple: bool) -> Style {
+        if components.is_empty() {
+            return Style {
+                header: multiple,
+                ..Style::default()
+            };
+        }
+        if components.contains(&StyleComponent::Plain) {
+            return Style::default();
+        }
+        Style {
+            numbers: components.contains(&StyleComponent::Numbers),
+            grid: components.contains(&StyleComponent::Grid),
+            header: components.contains(&StyleComponent::Header),
+            rule: components.contains(&StyleComponent::Rule),
+        }
+    }
+
+    pub fn has_gutter(&self) -> bool {
+        self.numbers || self.grid
+    }
+
+    /// Whether any of the decorations are drawn in the theme's colors.
+    pub fn uses_colors(&self) -> bool {
+        self.has_gutter() || self.rule
+    }
+}
+
+/// The header, gutter and rules of one printed file. The gutter width comes
+/// from the file's total line count, so it stays the same across chunks.
+pub struct Frame {
+    style: Style,
+    color: Option<String>,
+    width: usize,
+    term_width: usize,
+    next_line: usize,
+    at_line_start: bool,
+    emphasized: Vec<Range<usize>>,
+    emphasis_bg: String,
+    /// The current line, while it is emphasized and not yet complete.
+    pending: Option<Vec<u8>>,
+}
+
+impl Frame {
+    /// `color` is the SGR sequence for line numbers and rules; `None` draws
+    /// them uncolored.
+    pub fn new(style: Style, total_lines: usize, color: Option<String>) -> Frame {
+        let digits = total_lines.max(1).ilog10() as usize + 1;
+        Frame {
+            style,
+            color,
+            width: digits.max(MIN_NUMBER_WIDTH),
+            term_width: term_width(),
+            next_line: 1,
+            at_line_start: true,
+            emphasized: Vec::new(),
+            emphasis_bg: String::new(),
+            pending: None,
+        }
+    }
+
+    /// Paints the lines in `lines` (0-based) with the background `bg`.
+    pub fn with_emphasis(mut self, lines: Vec<Range<usize>>, bg: String) -> Frame {
+        self.emphasized = lines;
+        self.emphasis_bg = bg;
+        self
+    }
+
+    /// Separates this file from the previous one and prints its name.
+    pub fn header(&self, out: &mut impl Write, index: usize, name: &str) -> io::Result<()> {
+        if index > 0 {
+            if self.style.rule {
+                self.rule(out, None)?;
+            } else if self.style.header && !self.style.grid {
+                out.write_all(b"\n\n")?;
+            }
+        }
+        if !self.style.header {
+            return Ok(());
+        }
+        let label = format!("\x1b[1;34m:: {} ::\x1b[0m", name);
+        if self.style.grid {
+            self.rule(out, Some('┬'))?;
+            let pad = self.gutter_columns();
+            writeln!(out, "{:pad$}{} {}", "", self.paint("│"), label, pad = pad)?;
+            self.rule(out, Some('┼'))?;
+        } else {
+            writeln!(out, "{}", label)?;
+        }
+        Ok(())
+    }
+
+    /// Closes the grid opened by `header`.
+    pub fn footer(&self, out: &mut impl Write) -> io::Result<()> {
+        if self.style.grid && self.style.header {
+            if !self.at_line_start {
+                out.write_all(b"\n")?;
+            }
+            self.rule(out, Some('┴'))?;
+        }
+        Ok(())
+    }
+
+    /// Wraps `out` so that every line written through it gets the gutter.
+    pub fn writer<'a, W: Write>(&'a mut self, out: &'a mut W) -> FrameWriter<'a, W> {
+        FrameWriter { frame: self, out }
+    }
+
+    /// Columns taken by the line numbers, before the grid bar.
+    fn gutter_columns(&self) -> usize {
+        if self.style.numbers {
+            self.width + 1
+        } else {
+            0
+        }
+    }
+
+    fn rule(&self, out: &mut impl Write, junction: Option<char>) -> io::Result<()> {
+        let line = match junction {
+            Some(j) if self.style.grid => {
+                let left = self.gutter_columns();
+                let right = self.term_width.saturating_sub(left + 1);
+                format!("{}{}{}", "─".repeat(left), j, "─".repeat(right))
+            }
+            _ => "─".repeat(self.term_width),
+        };
+        writeln!(out, "{}", self.paint(&line))
+    }
+
+    fn paint(&self, text: &str) -> String {
+        match &self.color {
+            Some(c) => format!("{}{}{}", c, text, RESET),
+            None => text.to_string(),
+        }
+    }
+
+    fn is_emphasized(&self, line: usize) -> bool {
+        self.emphasized.iter().any(|r| r.contains(&(line - 1)))
+    }
+
+    fn gutter(&mut self) -> String {
+        let mut prefix = String::new();
+        if self.style.numbers {
+            let n = format!("{:>width$}", self.next_line, width = self.width);
+            prefix.push_str(&self.paint(&n));
+            prefix.push(' ');
+        }
+        if self.style.grid {
+            prefix.push_str(&self.paint("│"));
+            prefix.push(' ');
+        }
+        self.next_line += 1;
+        prefix
+    }
+}
+
+pub struct FrameWriter<'a, W: Write> {
+    frame: &'a mut Frame,
+    out: &'a mut W,
+}
+
+impl<W: Write> FrameWriter<'_, W> {
+    /// Numbers the next line `n`, for output that skips parts of the file.
+    pub fn set_line(&mut self, n: usize) {
+        self.frame.next_line = n;
+    }
+
+    /// Whether the last line written was finished with a newline.
+    pub fn at_line_start(&self) -> bool {
+        self.frame.at_line_start
+    }
+}
+
+impl<W: Write> FrameWriter<'_, W> {
+    fn write_line_part(&mut self, part: &[u8]) -> io::Result<()> {
+        match &mut self.frame.pending {
+            Some(line) => {
+                line.extend_from_slice(part);
+                Ok(())
+            }
+            None => self.out.write_all(part),
+        }
+    }
+
+    /// Writes out the emphasized line collected so far.
+    fn finish_line(&mut self) -> io::Result<()> {
+        match self.frame.pending.take() {
+            Some(line) => self
+                .out
+                .write_all(&emphasize(&line, &self.frame.emphasis_bg)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<W: Write> Write for FrameWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.frame.style.has_gutter() && self.frame.emphasized.is_empty() {
+            self.out.write_all(buf)?;
+            if let Some(&last) = buf.last() {
+                self.frame.at_line_start = last == b'\n';
+            }
+            return Ok(buf.len());
+        }
+        let mut rest = buf;
+        while !rest.is_empty() {
+            if self.frame.at_line_start {
+                if self.frame.is_emphasized(self.frame.next_line) {
+                    self.frame.pending = Some(Vec::new());
+                }
+                let prefix = self.frame.gutter();
+                self.out.write_all(prefix.as_bytes())?;
+                self.frame.at_line_start = false;
+            }
+            match rest.iter().position(|&b| b == b'\n') {
+                Some(i) => {
+                    self.write_line_part(&rest[..i])?;
+                    self.finish_line()?;
+                    self.out.write_all(b"\n")?;
+                    self.frame.at_line_start = true;
+                    rest = &rest[i + 1..];
+                }
+                None => {
+                    self.write_line_part(rest)?;
+                    break;
+                }
+            }
+        }
+        Ok(buf.len())
+    }
+
+    /// Also completes an emphasized last line that has no newline.
+    fn flush(&mut self) -> io::Result<()> {
+        self.finish_line()?;
+        self.out.flush()
+    }
+}
+
+/// Paints `line` with the background `bg` out to the right edge of the
+/// terminal, putting it back after every SGR sequence of the line's own that
+/// does not pick a background itself.
+pub fn emphasize(line: &[u8], bg: &str) -> Vec<u8> {
+    let mut out = bg.as_bytes().to_vec();
+    let mut i = 0;
+    while i < line.len() {
+        if line[i] == 0x1b
+            && line.get(i + 1) == Some(&b'[')
+            && let Some(len) = line[i + 2..].iter().position(|b| (0x40..=0x7e).contains(b))
+        {
+            let end = i + 2 + len;
+            out.extend_from_slice(&line[i..=end]);
+            if line[end] == b'm' && !sets_background(&line[i + 2..end]) {
+                out.extend_from_slice(bg.as_bytes());
+            }
+            i = end + 1;
+            continue;
+        }
+        out.push(line[i]);
+        i += 1;
+    }
+    out.extend_from_slice(b"\x1b[K\x1b[0m");
+    out
+}
+
+/// Whether the SGR parameters `params` (between `ESC [` and `m`) set a
+/// background color.
+fn sets_background(params: &[u8]) -> bool {
+    let params = String::from_utf8_lossy(params);
+    let mut parts = params.split([';', ':']);
+    while let Some(p) = parts.next() {
+        match p.parse::<u16>().unwrap_or(0) {
+            40..=47 | 48 | 100..=107 => return true,
+            // Extended foreground colors carry components that look like codes.
+            38 | 58 => match parts.next() {
+                Some("5") => {
+                    parts.next();
+                }
+                Some("2") => {
+                    parts.nth(2);
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+    false
+}
+
+fn term_width() -> usize {
+    if io::stdout().is_terminal()
+        && let Ok((cols, _)) = terminal::size()
+    {
+        return cols as usize;
+    }
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|c| c.parse().ok())
+        .filter(|&c| c > 0)
+        .unwrap_or(DEFAULT_TERM_WIDTH)
+}
@@ -0,0 +1,117 @@
+use std::collections::hash_map::RandomState;
+use std::fs::{self, File};
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+
+/// Every temp dir currently alive, so signal and panic handlers can remove
+/// them when the normal `Drop` path never runs.
+static LIVE_DIRS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// A private directory under the system temp dir: random name, created
+/// exclusively with mode 0700, and removed again on drop.
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    pub fn new(prefix: &str) -> io::Result<TempDir> {
+        let base = std::env::temp_dir();
+        for _ in 0..16 {
+            let path = base.join(format!("{}{:016x}", prefix, random_u64()));
+            let mut builder = fs::DirBuilder::new();
+            #[cfg(unix)]
+            builder.mode(0o700);
+            match builder.create(&path) {
+                Ok(()) => {
+                    if let Ok(mut live) = LIVE_DIRS.lock() {
+                        live.push(path.clone());
+                    }
+                    return Ok(TempDir { path });
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "could not find a free temp directory name",
+        ))
+    }
+
+    /// Creates `name` inside the directory with O_EXCL and mode 0600,
+    /// replacing a previous file of ours with the same name.
+    pub fn create_file(&self, name: &str) -> io::Result<(PathBuf, File)> {
+        let path = self.path.join(name);
+        let _ = fs::remove_file(&path);
+        let mut options = File::options();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let file = options.open(&path)?;
+        Ok((path, file))
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+        if let Ok(mut live) = LIVE_DIRS.lock() {
+            live.retain(|p| p != &self.path);
+        }
+    }
+}
+
+fn random_u64() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u32(std::process::id());
+    hasher.finish()
+}
+
+/// Removes every live temp dir. Safe to call more than once.
+pub fn cleanup_all() {
+    let dirs = match LIVE_DIRS.lock() {
+        Ok(mut live) => std::mem::take(&mut *live),
+        Err(poisoned) => std::mem::take(&mut *poisoned.into_inner()),
+    };
+    for dir in dirs {
+        let _ = fs::remove_dir_all(dir);
+    }
+}
+
+/// Makes sure temp dirs are removed on panics and on SIGINT/SIGTERM/SIGHUP,
+/// restoring the terminal first if the pager had it in raw mode.
+pub fn install_cleanup_handlers() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        cleanup_all();
+        default_hook(info);
+    }));
+
+    #[cfg(unix)]
+    {
+        use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+        use signal_hook::iterator::Signals;
+
+        if let Ok(mut signals) = Signals::new([SIGINT, SIGTERM, SIGHUP]) {
+            std::thread::spawn(move || {
+                if let Some(sig) = signals.forever().next() {
+                    cleanup_all();
+                    if crossterm::terminal::is_raw_mode_enabled().unwrap_or(false) {
+                        let _ = crossterm::execute!(
+                            io::stdout(),
+                            crossterm::cursor::Show,
+                            crossterm::terminal::LeaveAlternateScreen
+                        );
+                        let _ = crossterm::terminal::disable_raw_mode();
+                    }
+                    std::process::exit(128 + sig);
+                }
+            });
+        }
+    }
+}
@@ -1,14 +1,8 @@
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::{self, BufRead, BufReader, Read};
 use std::path::{Path, PathBuf};
 use std::process::Command;
-
-pub fn write_temp_lua_script(content: &str) -> io::Result<PathBuf> {
-    let mut path = std::env::temp_dir();
-    path.push(format!("meow_script_{}.lua", std::process::id()));
-    std::fs::write(&path, content)?;
-    Ok(path)
-}
+use std::time::SystemTime;
 
 pub fn is_binary_or_device(path: &Path) -> io::Result<bool> {
     let mut file = File::open(path)?;
@@ -23,16 +17,20 @@ pub fn is_binary_or_device(path: &Path) -> io::Result<bool> {
     Ok(false)
 }
 
-pub fn file_exceeds_terminal_height(path: &Path) -> io::Result<bool> {
+pub fn terminal_height() -> usize {
     let output = Command::new("tput").arg("lines").output();
 
-    let height = match output {
+    match output {
         Ok(o) if o.status.success() => String::from_utf8_lossy(&o.stdout)
             .trim()
             .parse::<usize>()
             .unwrap_or(24),
         _ => 24,
-    };
+    }
+}
+
+pub fn file_exceeds_terminal_height(path: &Path) -> io::Result<bool> {
+    let height = terminal_height();
 
     let file = File::open(path)?;
     let reader = BufReader::new(file);
@@ -47,3 +45,116 @@ pub fn file_exceeds_terminal_height(path: &Path) -> io::Result<bool> {
 
     Ok(false)
 }
+
+/// Matches `text` against a shell-style glob supporting `*`, `**` and `?`.
+/// Patterns without a `/` are matched against the basename only.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let text = if pattern.contains('/') {
+        text
+    } else {
+        text.rsplit('/').next().unwrap_or(text)
+    };
+    glob_match_bytes(pattern.as_bytes(), text.as_bytes())
+}
+
+fn glob_match_bytes(pat: &[u8], text: &[u8]) -> bool {
+    match pat.first() {
+        None => text.is_empty(),
+        Some(b'*') if pat.get(1) == Some(&b'*') => match pat[2..].strip_prefix(b"/") {
+            Some(rest) => (0..=text.len())
+                .filter(|&i| i == 0 || text[i - 1] == b'/')
+                .any(|i| glob_match_bytes(rest, &text[i..])),
+            None => (0..=text.len()).any(|i| glob_match_bytes(&pat[2..], &text[i..])),
+        },
+        Some(b'*') => (0..=text.len())
+            .take_while(|&i| i == 0 || text[i - 1] != b'/')
+            .any(|i| glob_match_bytes(&pat[1..], &text[i..])),
+        Some(b'?') => {
+            !text.is_empty() && text[0] != b'/' && glob_match_bytes(&pat[1..], &text[1..])
+        }
+        Some(&c) => text.first() == Some(&c) && glob_match_bytes(&pat[1..], &text[1..]),
+    }
+}
+
+pub fn find_in_path(bin: &Path) -> Option<PathBuf> {
+    let paths = std::env::var_os("PATH")?;
+    std::env::split_paths(&paths)
+        .map(|dir| dir.join(bin))
+        .find(|candidate| candidate.is_file())
+}
+
+/// Where `bin` would be run from: a path is taken as is, a bare name is
+/// looked up in `PATH`.
+pub fn resolve_program(bin: &Path) -> Option<PathBuf> {
+    if bin.components().count() > 1 {
+        bin.is_file().then(|| bin.to_path_buf())
+    } else {
+        find_in_path(bin)
+    }
+}
+
+/// Stand-in for the Neovim version: the resolved binary plus its size and
+/// mtime, which change on every upgrade without having to spawn `nvim`.
+pub fn nvim_fingerprint(bin: &Path) -> String {
+    let Some(bin) = resolve_program(bin) else {
+        return String::new();
+    };
+    let bin = bin.canonicalize().unwrap_or(bin);
+    let Ok(meta) = fs::metadata(&bin) else {
+        return bin.display().to_string();
+    };
+    let mtime = meta
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_secs());
+    format!("{}:{}:{}", bin.display(), meta.len(), mtime)
+}
+
+/// The first `len` bytes of the file, the size it had when it was looked at;
+/// anything written since is left for a follower to pick up.
+pub fn read_up_to(path: &Path, len: u64) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    File::open(path)?.take(len).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+/// Number of lines in the file, counting a final line without a newline.
+pub fn count_lines(path: &Path) -> io::Result<usize> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut count = 0;
+    let mut last = b'\n';
+    loop {
+        let buf = reader.fill_buf()?;
+        let Some(&end) = buf.last() else { break };
+        count += buf.iter().filter(|&&b| b == b'\n').count();
+        last = end;
+        let n = buf.len();
+        reader.consume(n);
+    }
+    Ok(count + usize::from(last != b'\n'))
+}
+
+/// The part of `data` after its first `n` newline-terminated lines.
+pub fn skip_lines(data: &[u8], n: usize) -> &[u8] {
+    let mut rest = data;
+    for _ in 0..n {
+        match rest.iter().position(|&b| b == b'\n') {
+            Some(pos) => rest = &rest[pos + 1..],
+            None => return &[],
+        }
+    }
+    rest
+}
+
+/// Splits on `\n` the way Neovim loads a buffer: no trailing empty line for a
+/// final newline, and a `\r` before each newline is dropped.
+pub fn split_lines(data: &[u8]) -> Vec<&[u8]> {
+    let data = data.strip_suffix(b"\n").unwrap_or(data);
+    if data.is_empty() {
+        return Vec::new();
+    }
+    data.split(|&b| b == b'\n')
+        .map(|l| l.strip_suffix(b"\r").unwrap_or(l))
+        .collect()
+}
@@ -0,0 +1,143 @@
+#![cfg(unix)]
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const FIRST: &[u8] = b"\n\n\nfn main() {\n\tlet s = \"caf\xc3\xa9\";\r\n\n\n\n\x01\x7f\x89\xff\x1b[31mred\x1b[0m\n    indented\ttab\n\n";
+const SECOND: &[u8] = b"\n\nsecond\r\nno newline at end";
+
+const FLAGS: &[&[&str]] = &[
+    &["-n"],
+    &["-b"],
+    &["-s"],
+    &["-E"],
+    &["-T"],
+    &["-v"],
+    &["-A"],
+    &["-ns"],
+    &["-bE"],
+    &["-nb"],
+    &["-sA"],
+    &["-n", "-T", "-v"],
+];
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("meow-test-cat-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("first.txt"), FIRST).unwrap();
+    fs::write(dir.join("second.txt"), SECOND).unwrap();
+    dir
+}
+
+fn is_gnu_cat() -> bool {
+    Command::new("cat")
+        .arg("--version")
+        .output()
+        .is_ok_and(|o| String::from_utf8_lossy(&o.stdout).contains("GNU coreutils"))
+}
+
+/// Runs meow without a usable Neovim, so it takes the raw path.
+fn meow(scratch: &Path, args: &[&str], files: &[PathBuf]) -> Vec<u8> {
+    let out = Command::new(env!("CARGO_BIN_EXE_meow"))
+        .env("XDG_CACHE_HOME", scratch.join("cache"))
+        .env("MEOW_THEME", "habamax")
+        .args(["--nvim", "/nonexistent/nvim", "--pager", "never"])
+        .args(args)
+        .args(files)
+        .output()
+        .unwrap();
+    assert!(out.status.success(), "meow {:?} failed", args);
+
+    // Drop the per-file headers; everything else must match cat byte for byte.
+    let mut stdout = out.stdout;
+    for (i, file) in files.iter().enumerate().filter(|_| files.len() > 1) {
+        let sep = if i > 0 { "\n\n" } else { "" };
+        let header = format!("{}\x1b[1;34m:: {} ::\x1b[0m\n", sep, file.display());
+        let at = stdout
+            .windows(header.len())
+            .position(|w| w == header.as_bytes())
+            .expect("header");
+        stdout.drain(at..at + header.len());
+    }
+    stdout
+}
+
+fn cat(args: &[&str], files: &[PathBuf]) -> Vec<u8> {
+    Command::new("cat")
+        .args(args)
+        .args(files)
+        .output()
+        .unwrap()
+        .stdout
+}
+
+#[test]
+fn flags_match_gnu_cat() {
+    if !is_gnu_cat() {
+        eprintln!("skipping: GNU cat not available");
+        return;
+    }
+    let scratch = scratch_dir("single");
+    let first = scratch.join("first.txt");
+    let second = scratch.join("second.txt");
+
+    for flags in FLAGS {
+        for files in [vec![first.clone()], vec![second.clone()]] {
+            assert_eq!(
+                String::from_utf8_lossy(&meow(&scratch, flags, &files)),
+                String::from_utf8_lossy(&cat(flags, &files)),
+                "meow {:?} {:?}",
+                flags,
+                files
+            );
+        }
+    }
+    fs::remove_dir_all(&scratch).unwrap();
+}
+
+#[test]
+fn numbering_and_squeezing_continue_across_files() {
+    if !is_gnu_cat() {
+        eprintln!("skipping: GNU cat not available");
+        return;
+    }
+    let scratch = scratch_dir("multi");
+    let files = [scratch.join("first.txt"), scratch.join("second.txt")];
+
+    for flags in FLAGS {
+        assert_eq!(
+            String::from_utf8_lossy(&meow(&scratch, flags, &files)),
+            String::from_utf8_lossy(&cat(flags, &files)),
+            "meow {:?}",
+            flags
+        );
+    }
+    fs::remove_dir_all(&scratch).unwrap();
+}
+
+#[test]
+fn standard_input_is_formatted_too() {
+    if !is_gnu_cat() {
+        eprintln!("skipping: GNU cat not available");
+        return;
+    }
+    let scratch = scratch_dir("stdin");
+    let out = Command::new("sh")
+        .arg("-c")
+        .arg(format!(
+            "{} --nvim /nonexistent/nvim --pager never -A < {}",
+            env!("CARGO_BIN_EXE_meow"),
+            scratch.join("first.txt").display()
+        ))
+        .env("XDG_CACHE_HOME", scratch.join("cache"))
+        .env("MEOW_THEME", "habamax")
+        .output()
+        .unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&out.stdout),
+        String::from_utf8_lossy(&cat(&["-A"], &[scratch.join("first.txt")]))
+    );
+    fs::remove_dir_all(&scratch).unwrap();
+}
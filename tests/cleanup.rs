@@ -0,0 +1,86 @@
+#![cfg(unix)]
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("meow-test-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("tmp")).unwrap();
+    dir
+}
+
+fn meow(scratch: &Path) -> Command {
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_meow"));
+    cmd.env("TMPDIR", scratch.join("tmp"))
+        .env("XDG_CACHE_HOME", scratch.join("cache"))
+        .env("MEOW_THEME", "habamax")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    cmd
+}
+
+fn entries(dir: &Path) -> usize {
+    fs::read_dir(dir).unwrap().count()
+}
+
+#[test]
+fn interrupted_run_leaves_no_temp_files() {
+    for signal in ["INT", "TERM"] {
+        let scratch = scratch_dir(signal);
+        let tmp = scratch.join("tmp");
+
+        // `--force-color` spools stdin right away; keeping the pipe open
+        // leaves meow blocked mid-spool until the signal arrives.
+        let mut child = meow(&scratch)
+            .args(["--force-color", "--pager", "never", "-"])
+            .stdin(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut stdin = child.stdin.take().unwrap();
+        stdin.write_all(b"fn main() {}\n").unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while entries(&tmp) == 0 {
+            assert!(Instant::now() < deadline, "spool dir never appeared");
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let sent = Command::new("kill")
+            .arg(format!("-{}", signal))
+            .arg(child.id().to_string())
+            .status()
+            .unwrap();
+        assert!(sent.success());
+        let status = child.wait().unwrap();
+        drop(stdin);
+
+        assert!(!status.success(), "SIG{} should not exit cleanly", signal);
+        assert_eq!(entries(&tmp), 0, "SIG{} left temp files behind", signal);
+        fs::remove_dir_all(&scratch).unwrap();
+    }
+}
+
+#[test]
+fn finished_run_leaves_no_temp_files() {
+    let scratch = scratch_dir("done");
+    let mut child = meow(&scratch)
+        .args(["--pager", "never", "--file-name", "x.rs", "-"])
+        .stdin(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"fn main() {}\n")
+        .unwrap();
+    child.wait().unwrap();
+
+    assert_eq!(entries(&scratch.join("tmp")), 0);
+    fs::remove_dir_all(&scratch).unwrap();
+}